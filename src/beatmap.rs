@@ -0,0 +1,74 @@
+use std::{fmt, path::PathBuf};
+
+use bevy::{
+    asset::{
+        io::{file::FileAssetReader, Reader},
+        AssetLoader, AsyncReadExt, LoadContext,
+    },
+    utils::BoxedFuture,
+};
+use machitan_format::file::parse_chart;
+
+use crate::note::Beatmap;
+
+// loads toml charts through the AssetServer so they can be hot reloaded
+#[derive(Default)]
+pub struct BeatmapLoader;
+
+#[derive(Debug)]
+pub enum BeatmapLoaderError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+}
+
+impl fmt::Display for BeatmapLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatmapLoaderError::Io(e) => write!(f, "could not read beatmap: {}", e),
+            BeatmapLoaderError::Toml(e) => write!(f, "could not parse beatmap: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BeatmapLoaderError {}
+
+impl From<std::io::Error> for BeatmapLoaderError {
+    fn from(e: std::io::Error) -> Self {
+        BeatmapLoaderError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for BeatmapLoaderError {
+    fn from(e: toml::de::Error) -> Self {
+        BeatmapLoaderError::Toml(e)
+    }
+}
+
+impl AssetLoader for BeatmapLoader {
+    type Asset = Beatmap;
+    type Settings = ();
+    type Error = BeatmapLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let source = String::from_utf8_lossy(&bytes);
+            Ok(Beatmap(parse_chart(&source)?))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["toml"]
+    }
+}
+
+// where the AssetServer will look for a path given to a console command
+pub fn asset_path(file: &str) -> PathBuf {
+    FileAssetReader::get_base_path().join("assets").join(file)
+}
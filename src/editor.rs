@@ -1,13 +1,23 @@
-use std::{fs::File, io::Write};
+use std::collections::HashMap;
 
 use bevy::{input::keyboard::KeyCode, prelude::*};
-use bevy_console::{AddConsoleCommand, ConsoleCommand};
-use clap::Parser;
+use bevy_console::{AddConsoleCommand, ConsoleCommand, ConsoleOpen};
+use clap::{Parser, Subcommand, ValueEnum};
+use machitan_format::{
+    file::write_chart,
+    timing::{quantise_note, quantise_to, TimingPoint},
+    NoteId, NoteLane, LANES,
+};
 use serde::Serialize;
 
 use crate::{
-    note::{NoteId, NoteLane},
-    FramesCount,
+    beatmap::asset_path,
+    history::{ChartEdit, EditHistory},
+    loading::GameAssets,
+    metronome::Metronome,
+    note::{lane_for_key, lane_transforms, Beatmap, LANE_VERT_POS},
+    song::{frames_to_duration, PlaybackRate, RateScaled, SongClip},
+    ApplicationState, FramesCount,
 };
 
 pub struct EditorPlugin;
@@ -17,73 +27,430 @@ pub struct EditorSet;
 
 impl Plugin for EditorPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, record_key_presses.in_set(EditorSet))
-            .insert_resource(BeatmapRecord { notes: vec![] })
+        app.add_systems(OnEnter(ApplicationState::Editor), editor_setup)
+            .add_systems(OnExit(ApplicationState::Editor), editor_teardown)
+            .add_systems(
+                Update,
+                (
+                    editor_hotkeys.run_if(console_closed),
+                    record_key_presses.run_if(console_closed),
+                    handle_playback,
+                    play_editor_hitsounds,
+                    update_editor_status,
+                )
+                    .chain()
+                    .in_set(EditorSet),
+            )
+            .add_systems(
+                FixedPreUpdate,
+                advance_editor_frames.run_if(in_state(ApplicationState::Editor)),
+            )
+            .insert_resource(BeatmapRecord {
+                notes: vec![],
+                mode: RecordMode::Merge,
+                lanes: vec![],
+                from: 0,
+                to: None,
+            })
+            .insert_resource(EditorPlayback {
+                playing: false,
+                recording: false,
+                hitsounds: false,
+            })
+            .insert_resource(SnapSettings {
+                divisor: 16,
+                live: false,
+            })
+            .add_event::<PlaybackEvent>()
             .add_console_command::<SaveRecordingCommand, _>(
                 save_recording_command.in_set(EditorSet),
             )
-            .add_console_command::<RecordCommand, _>(record_command);
+            .add_console_command::<RecordCommand, _>(record_command)
+            .add_console_command::<HitsoundsCommand, _>(hitsounds_command)
+            .add_console_command::<SnapCommand, _>(snap_command)
+            .add_console_command::<QuantiseCommand, _>(quantise_command)
+            .add_console_command::<TimingCommand, _>(timing_command);
     }
 }
 
+// the take being recorded, added to the chart as one edit once recording stops
 #[derive(Resource, Serialize)]
-struct BeatmapRecord {
-    notes: Vec<NoteId>,
+pub(crate) struct BeatmapRecord {
+    pub notes: Vec<NoteId>,
+    #[serde(skip)]
+    mode: RecordMode,
+    // lanes taking part in the take, empty for all of them
+    #[serde(skip)]
+    lanes: Vec<NoteLane>,
+    #[serde(skip)]
+    from: usize,
+    // punch-out frame, recording stops there on its own
+    #[serde(skip)]
+    to: Option<usize>,
+}
+
+impl BeatmapRecord {
+    fn records(&self, lane: &NoteLane) -> bool {
+        self.lanes.is_empty() || self.lanes.contains(lane)
+    }
+}
+
+// how a take goes into the chart
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Debug)]
+enum RecordMode {
+    // add the take on top of the chart
+    Merge,
+    // clear every lane over the recorded span first
+    Replace,
+    // clear only the recorded lanes over the span, needs --lanes
+    Overdub,
+    // replace only between --from and --to, with a pre-roll to play along to
+    Punch,
+}
+
+// how much of the song plays before a punch-in starts recording
+const PRE_ROLL: usize = 120;
+
+// everything spawned for the editor, despawned when leaving it
+#[derive(Component)]
+pub(crate) struct EditorTag;
+
+#[derive(Component)]
+pub(crate) struct EditorCamera;
+
+#[derive(Component)]
+struct EditorStatusText;
+
+#[derive(Component)]
+struct EditorSongTag;
+
+// the song clock only runs in the editor while playing back or recording,
+// otherwise FramesCount is the timeline cursor
+#[derive(Resource)]
+pub(crate) struct EditorPlayback {
+    pub playing: bool,
+    pub recording: bool,
+    // play the lane hitsound as existing notes pass the playhead
+    pub hitsounds: bool,
+}
+
+// snap used by the timeline grid and to quantise recorded notes,
+// the tempo comes from the chart's timing points
+#[derive(Resource)]
+pub(crate) struct SnapSettings {
+    pub divisor: u32,
+    // snap notes as they're recorded instead of afterwards with `quantise`
+    pub live: bool,
+}
+
+#[derive(Event)]
+pub(crate) enum PlaybackEvent {
+    Toggle,
+    Start,
+    Stop,
+    // the cursor moved, so the song has to be restarted from there
+    Seek,
+}
+
+fn editor_setup(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    mut frames: ResMut<FramesCount>,
+    mut playback: ResMut<EditorPlayback>,
+    mut cameras: Query<&mut Camera, Without<EditorCamera>>,
+) {
+    // the editor brings its own camera, so switch the game one off until we leave
+    for mut camera in cameras.iter_mut() {
+        camera.is_active = false;
+    }
+    commands.spawn((
+        EditorTag,
+        EditorCamera,
+        Camera2dBundle {
+            camera: Camera {
+                order: 1,
+                ..default()
+            },
+            ..default()
+        },
+    ));
+
+    for lane in LANES.iter() {
+        commands.spawn((
+            EditorTag,
+            SpriteBundle {
+                texture: assets.note_frame.clone(),
+                transform: lane_transforms(lane, Some(LANE_VERT_POS)),
+                ..default()
+            },
+        ));
+    }
+
+    commands.spawn((
+        EditorTag,
+        EditorStatusText,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                color: Color::ALICE_BLUE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+    ));
+
+    // the editor's timeline starts right at the song
+    frames.count = 0;
+    frames.lead_in = 0;
+    playback.playing = false;
+    playback.recording = false;
+}
+
+fn editor_teardown(
+    mut commands: Commands,
+    query: Query<Entity, With<EditorTag>>,
+    mut cameras: Query<&mut Camera, Without<EditorCamera>>,
+    mut playback: ResMut<EditorPlayback>,
+    frames: Res<FramesCount>,
+    beatmap_record: Res<BeatmapRecord>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+) {
+    // don't lose a take that was still recording
+    if playback.recording {
+        commit_take(&beatmap_record, frames.count, &mut history, &mut beatmap);
+        playback.recording = false;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for mut camera in cameras.iter_mut() {
+        camera.is_active = true;
+    }
+}
+
+fn update_editor_status(
+    frames: Res<FramesCount>,
+    beatmap_record: Res<BeatmapRecord>,
+    playback: Res<EditorPlayback>,
+    history: Res<EditHistory>,
+    rate: Res<PlaybackRate>,
+    mut query: Query<&mut Text, With<EditorStatusText>>,
+) {
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "EDITOR {} at {}x  frame {}  recorded {} notes{}{}",
+            if playback.recording {
+                "RECORDING"
+            } else if playback.playing {
+                "PLAYING"
+            } else {
+                "PAUSED"
+            },
+            rate.0,
+            frames.count,
+            beatmap_record.notes.len(),
+            if playback.hitsounds {
+                "  hitsounds on"
+            } else {
+                ""
+            },
+            if history.dirty { "  (unsaved)" } else { "" }
+        );
+    }
+}
+
+// commands typed into the console have spaces and lane letters in them
+pub(crate) fn console_closed(console: Res<ConsoleOpen>) -> bool {
+    !console.open
+}
+
+fn advance_editor_frames(playback: Res<EditorPlayback>, mut frames: ResMut<FramesCount>) {
+    if playback.playing {
+        frames.count += 1;
+    }
+}
+
+fn editor_hotkeys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut ev_playback: EventWriter<PlaybackEvent>,
+    mut playback: ResMut<EditorPlayback>,
+    mut metronome: ResMut<Metronome>,
+) {
+    if keys.just_pressed(KeyCode::Space) {
+        ev_playback.send(PlaybackEvent::Toggle);
+    }
+    if keys.just_pressed(KeyCode::KeyH) {
+        playback.hitsounds = !playback.hitsounds;
+    }
+    if keys.just_pressed(KeyCode::KeyM) {
+        metronome.audible = !metronome.audible;
+    }
+}
+
+fn play_editor_hitsounds(
+    mut commands: Commands,
+    mut last: Local<usize>,
+    frames: Res<FramesCount>,
+    playback: Res<EditorPlayback>,
+    assets: Res<GameAssets>,
+    beatmap: Res<Beatmap>,
+) {
+    // only notes passed since the last frame, seeking just moves the mark
+    let passed = *last..frames.count;
+    *last = frames.count;
+    if !playback.playing || !playback.hitsounds || passed.is_empty() {
+        return;
+    }
+
+    for note in beatmap
+        .notes
+        .iter()
+        .filter(|note| passed.contains(&note.timing))
+    {
+        commands.spawn((
+            EditorTag,
+            AudioBundle {
+                source: assets.hitsound(&note.lane),
+                settings: PlaybackSettings::DESPAWN,
+            },
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_playback(
+    mut commands: Commands,
+    mut ev_playback: EventReader<PlaybackEvent>,
+    mut playback: ResMut<EditorPlayback>,
+    frames: Res<FramesCount>,
+    assets: Res<GameAssets>,
+    songs: Res<Assets<AudioSource>>,
+    mut clips: ResMut<Assets<SongClip>>,
+    rate: Res<PlaybackRate>,
+    song_query: Query<Entity, With<EditorSongTag>>,
+    beatmap_record: Res<BeatmapRecord>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+) {
+    let mut restart = false;
+    for ev in ev_playback.read() {
+        match ev {
+            PlaybackEvent::Toggle => playback.playing = !playback.playing,
+            PlaybackEvent::Start => playback.playing = true,
+            PlaybackEvent::Stop => playback.playing = false,
+            PlaybackEvent::Seek => {}
+        }
+        restart = true;
+    }
+    if !restart {
+        return;
+    }
+
+    for entity in song_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if !playback.playing {
+        if playback.recording {
+            commit_take(&beatmap_record, frames.count, &mut history, &mut beatmap);
+            playback.recording = false;
+        }
+        return;
+    }
+    // the song follows the cursor, so start it from wherever that is
+    if let Some(song) = assets.song.as_ref().and_then(|handle| songs.get(handle)) {
+        commands.spawn((
+            EditorTag,
+            EditorSongTag,
+            RateScaled,
+            AudioSourceBundle {
+                source: clips.add(SongClip::new(song, frames_to_duration(frames.count))),
+                settings: rate.settings(PlaybackSettings::DESPAWN),
+            },
+        ));
+    }
+}
+
+// the take becomes one edit, so a bad pass is a single undo away
+fn commit_take(
+    beatmap_record: &BeatmapRecord,
+    stopped_at: usize,
+    history: &mut EditHistory,
+    beatmap: &mut Beatmap,
+) {
+    let to = beatmap_record.to.unwrap_or(stopped_at);
+    let replaced: Vec<NoteId> = match beatmap_record.mode {
+        RecordMode::Merge => vec![],
+        _ => beatmap
+            .notes
+            .iter()
+            .filter(|note| {
+                note.timing >= beatmap_record.from
+                    && note.timing <= to
+                    && beatmap_record.records(&note.lane)
+            })
+            .cloned()
+            .collect(),
+    };
+    let edit = ChartEdit::notes("record", beatmap, &replaced, &beatmap_record.notes);
+    history.apply(beatmap, edit);
 }
 
+// a lane key held down at least this long while recording makes a hold
+const MIN_HOLD: usize = 15;
+
 fn record_key_presses(
+    // where each lane's held note is in the take
+    mut held: Local<HashMap<NoteLane, usize>>,
     keys: Res<ButtonInput<KeyCode>>,
     frames: Res<FramesCount>,
+    playback: Res<EditorPlayback>,
+    snap: Res<SnapSettings>,
+    beatmap: Res<Beatmap>,
     mut beatmap_record: ResMut<BeatmapRecord>,
+    mut ev_playback: EventWriter<PlaybackEvent>,
 ) {
+    if !playback.recording || !playback.playing {
+        held.clear();
+        return;
+    }
+    // still in the pre-roll
+    if frames.count < beatmap_record.from {
+        return;
+    }
+    if beatmap_record.to.is_some_and(|to| frames.count > to) {
+        ev_playback.send(PlaybackEvent::Stop);
+        return;
+    }
+
+    let timing = if snap.live {
+        quantise_to(&beatmap.timing_points, frames.count, snap.divisor)
+    } else {
+        frames.count
+    };
+
     for key in keys.get_just_pressed() {
-        if key == &KeyCode::KeyA {
-            beatmap_record.notes.push(NoteId {
-                timing: frames.count,
-                lane: NoteLane::LaneA,
-            });
-        }
-        if key == &KeyCode::KeyS {
-            beatmap_record.notes.push(NoteId {
-                timing: frames.count,
-                lane: NoteLane::LaneS,
-            });
-        }
-        if key == &KeyCode::KeyD {
-            beatmap_record.notes.push(NoteId {
-                timing: frames.count,
-                lane: NoteLane::LaneD,
-            });
-        }
-        if key == &KeyCode::KeyF {
-            beatmap_record.notes.push(NoteId {
-                timing: frames.count,
-                lane: NoteLane::LaneF,
-            });
-        }
-        if key == &KeyCode::KeyJ {
-            beatmap_record.notes.push(NoteId {
-                timing: frames.count,
-                lane: NoteLane::LaneJ,
-            });
-        }
-        if key == &KeyCode::KeyK {
-            beatmap_record.notes.push(NoteId {
-                timing: frames.count,
-                lane: NoteLane::LaneK,
-            });
-        }
-        if key == &KeyCode::KeyL {
-            beatmap_record.notes.push(NoteId {
-                timing: frames.count,
-                lane: NoteLane::LaneL,
-            });
-        }
-        if key == &KeyCode::Semicolon {
-            beatmap_record.notes.push(NoteId {
-                timing: frames.count,
-                lane: NoteLane::LaneSemicolon,
-            });
+        if let Some(lane) = lane_for_key(key) {
+            if beatmap_record.records(&lane) {
+                held.insert(lane, beatmap_record.notes.len());
+                beatmap_record.notes.push(NoteId::tap(timing, lane));
+            }
+        }
+    }
+    for key in keys.get_just_released() {
+        let Some(index) = lane_for_key(key).and_then(|lane| held.remove(&lane)) else {
+            continue;
+        };
+        if let Some(note) = beatmap_record.notes.get_mut(index) {
+            if timing >= note.timing + MIN_HOLD {
+                note.end = Some(timing);
+            }
         }
     }
 }
@@ -99,31 +466,296 @@ fn save_recording_command(
     mut beatmap_record: Res<BeatmapRecord>,
 ) {
     if let Some(Ok(SaveRecordingCommand { dest })) = log.take() {
-        let mut file = File::create(&dest).unwrap();
-        let toml_beatmap = toml::to_string(beatmap_record.as_ref()).unwrap();
-        file.write_all(toml_beatmap.as_bytes()).unwrap();
-        log.reply(format!("Saved current beatmap as {}", dest));
+        match write_chart(&asset_path(&dest), beatmap_record.as_ref()) {
+            Ok(()) => log.reply(format!("Saved current beatmap as {}", dest)),
+            Err(e) => log.reply(format!("Could not save recording: {}", e)),
+        }
     }
 }
 
 #[derive(Parser, ConsoleCommand)]
 #[command(name = "record")]
-struct RecordCommand;
+struct RecordCommand {
+    #[arg(long, value_enum, default_value_t = RecordMode::Merge)]
+    mode: RecordMode,
+    // lane keys to record, `--lanes jkl` only takes the right hand
+    #[arg(long)]
+    lanes: Option<String>,
+    #[arg(long, default_value_t = 0)]
+    from: usize,
+    #[arg(long)]
+    to: Option<usize>,
+}
 
 fn record_command(
-    mut commands: Commands,
     mut log: ConsoleCommand<RecordCommand>,
     mut frames: ResMut<FramesCount>,
-    server: Res<AssetServer>,
+    mut ev_playback: EventWriter<PlaybackEvent>,
+    mut playback: ResMut<EditorPlayback>,
+    mut beatmap_record: ResMut<BeatmapRecord>,
+    assets: Res<GameAssets>,
+    state: Res<State<ApplicationState>>,
 ) {
-    if let Some(Ok(RecordCommand)) = log.take() {
-        commands.spawn(AudioBundle {
-            source: server.load("make_debut_TV_size.ogg"),
-            ..default()
-        });
+    if let Some(Ok(RecordCommand {
+        mode,
+        lanes,
+        from,
+        to,
+    })) = log.take()
+    {
+        if state.get() != &ApplicationState::Editor {
+            log.reply("Recording only works in the editor, try `mode editor`");
+            return;
+        }
+        if playback.recording {
+            log.reply("Already recording, press space to stop");
+            return;
+        }
+
+        let mut record_lanes = vec![];
+        for c in lanes.unwrap_or_default().chars() {
+            match NoteLane::from_char(c) {
+                Some(lane) => record_lanes.push(lane),
+                None => {
+                    log.reply(format!("{} isn't a lane key, use asdfjkl;", c));
+                    return;
+                }
+            }
+        }
+        if mode == RecordMode::Overdub && record_lanes.is_empty() {
+            log.reply("Overdubbing needs the lanes to record, like --lanes asdf");
+            return;
+        }
+        if mode == RecordMode::Punch && to.is_none() {
+            log.reply("Punching in needs a range, like --from 600 --to 1200");
+            return;
+        }
+        if to.is_some_and(|to| to <= from) {
+            log.reply("--to has to be after --from");
+            return;
+        }
+
+        beatmap_record.notes.clear();
+        beatmap_record.mode = mode;
+        beatmap_record.lanes = record_lanes;
+        beatmap_record.from = from;
+        beatmap_record.to = to;
+        playback.recording = true;
 
-        // reinitialize framecount to 0
-        // log.reply(format!("orig framecount: {}", frames.count));
-        frames.count = 0;
+        ev_playback.send(PlaybackEvent::Start);
+        match &assets.song {
+            Some(_) => log.reply(format!("Recording ({:?})!", mode)),
+            None => log.reply("Recording without a song, use `load` to pick one"),
+        }
+
+        // punching in plays a bit of the song first to get into the rhythm
+        frames.count = match mode {
+            RecordMode::Punch => from.saturating_sub(PRE_ROLL),
+            _ => from,
+        };
+    }
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "hitsounds")]
+struct HitsoundsCommand {
+    // toggles when left out
+    #[arg(action = clap::ArgAction::Set)]
+    enabled: Option<bool>,
+}
+
+fn hitsounds_command(
+    mut log: ConsoleCommand<HitsoundsCommand>,
+    mut playback: ResMut<EditorPlayback>,
+) {
+    if let Some(Ok(HitsoundsCommand { enabled })) = log.take() {
+        playback.hitsounds = enabled.unwrap_or(!playback.hitsounds);
+        log.reply(format!("Editor hitsounds {}", playback.hitsounds));
+    }
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "snap")]
+struct SnapCommand {
+    // note value to snap to, 4 for 1/4, 12 for 1/12 and so on
+    divisor: Option<u32>,
+    #[arg(long, action = clap::ArgAction::Set)]
+    live: Option<bool>,
+}
+
+fn snap_command(mut log: ConsoleCommand<SnapCommand>, mut snap: ResMut<SnapSettings>) {
+    if let Some(Ok(SnapCommand { divisor, live })) = log.take() {
+        if divisor == Some(0) {
+            log.reply("Snap divisor has to be above 0");
+            return;
+        }
+
+        if let Some(divisor) = divisor {
+            snap.divisor = divisor;
+        }
+        if let Some(live) = live {
+            snap.live = live;
+        }
+        log.reply(format!(
+            "Snapping to 1/{}, live {}",
+            snap.divisor, snap.live
+        ));
+    }
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "quantise")]
+struct QuantiseCommand {
+    // overrides the snap divisor for this pass
+    divisor: Option<u32>,
+}
+
+fn quantise_command(
+    mut log: ConsoleCommand<QuantiseCommand>,
+    snap: Res<SnapSettings>,
+    playback: Res<EditorPlayback>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+    mut beatmap_record: ResMut<BeatmapRecord>,
+) {
+    if let Some(Ok(QuantiseCommand { divisor })) = log.take() {
+        if playback.recording {
+            log.reply("Stop recording before quantising the take");
+            return;
+        }
+
+        let divisor = divisor.unwrap_or(snap.divisor).max(1);
+        let mut moved = 0;
+        let mut furthest = 0;
+
+        // the take is already in the chart, so move it there too. Notes of the take that
+        // were undone, deleted or moved since aren't in the chart anymore and stay put
+        let mut before = vec![];
+        let mut after = vec![];
+        let mut quantised = 0;
+        for note in beatmap_record.notes.iter_mut() {
+            if !beatmap.notes.contains(note) {
+                continue;
+            }
+            quantised += 1;
+            let snapped = quantise_note(&beatmap.timing_points, note, divisor);
+            if snapped == *note {
+                continue;
+            }
+            let distance = snapped.timing as isize - note.timing as isize;
+            log.reply(format!(
+                "{:?} {} -> {} ({:+})",
+                note.lane, note.timing, snapped.timing, distance
+            ));
+            moved += 1;
+            furthest = furthest.max(distance.unsigned_abs());
+            before.push(note.clone());
+            *note = snapped.clone();
+            after.push(snapped);
+        }
+        beatmap_record.notes.sort_by_key(|note| note.timing);
+        let mut merged = 0;
+        if moved > 0 {
+            let edit = ChartEdit::notes("quantise", &beatmap, &before, &after);
+            merged = history.apply(&mut beatmap, edit);
+        }
+
+        log.reply(format!(
+            "Quantised to 1/{}: moved {} of {} notes, furthest by {} frames",
+            divisor, moved, quantised, furthest
+        ));
+        if merged > 0 {
+            log.reply(format!(
+                "{} notes landed on notes already in the chart and were merged into them",
+                merged
+            ));
+        }
+    }
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "timing")]
+struct TimingCommand {
+    #[command(subcommand)]
+    action: TimingAction,
+}
+
+#[derive(Subcommand)]
+enum TimingAction {
+    List,
+    // adds a tempo section starting at a frame, replacing one already there
+    Add {
+        timing: f64,
+        bpm: f64,
+        #[arg(long, default_value_t = 4)]
+        beats_per_measure: u32,
+        #[arg(long, default_value_t = 4)]
+        beat_unit: u32,
+    },
+    Remove {
+        index: usize,
+    },
+}
+
+fn timing_command(
+    mut log: ConsoleCommand<TimingCommand>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+) {
+    if let Some(Ok(TimingCommand { action })) = log.take() {
+        match action {
+            TimingAction::List => {
+                if beatmap.timing_points.is_empty() {
+                    log.reply("No timing points, the grid defaults to 120 bpm in 4/4");
+                }
+                for (index, point) in beatmap.timing_points.iter().enumerate() {
+                    log.reply(format!(
+                        "{}: frame {} at {} bpm in {}/{}",
+                        index, point.timing, point.bpm, point.beats_per_measure, point.beat_unit
+                    ));
+                }
+            }
+            TimingAction::Add {
+                timing,
+                bpm,
+                beats_per_measure,
+                beat_unit,
+            } => {
+                if !bpm.is_finite()
+                    || bpm <= 0.0
+                    || !timing.is_finite()
+                    || timing < 0.0
+                    || beats_per_measure == 0
+                    || beat_unit == 0
+                {
+                    log.reply("Timing points need a positive frame, bpm and time signature");
+                    return;
+                }
+
+                let mut points = beatmap.timing_points.clone();
+                points.retain(|point| point.timing != timing);
+                points.push(TimingPoint {
+                    timing,
+                    bpm,
+                    beats_per_measure,
+                    beat_unit,
+                });
+                points.sort_by(|a, b| a.timing.total_cmp(&b.timing));
+                let edit = ChartEdit::timing("add timing point", &beatmap, points);
+                history.apply(&mut beatmap, edit);
+                log.reply(format!("Added {} bpm at frame {}", bpm, timing));
+            }
+            TimingAction::Remove { index } => {
+                if index < beatmap.timing_points.len() {
+                    let mut points = beatmap.timing_points.clone();
+                    points.remove(index);
+                    let edit = ChartEdit::timing("remove timing point", &beatmap, points);
+                    history.apply(&mut beatmap, edit);
+                    log.reply(format!("Removed timing point {}", index));
+                } else {
+                    log.reply(format!("No timing point {}", index));
+                }
+            }
+        }
     }
 }
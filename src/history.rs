@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_console::{AddConsoleCommand, ConsoleCommand};
+use clap::Parser;
+use machitan_format::{timing::TimingPoint, NoteId};
+
+use crate::{
+    editor::{console_closed, EditorSet},
+    note::Beatmap,
+    ApplicationState,
+};
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditHistory>()
+            .add_systems(
+                Update,
+                history_hotkeys.run_if(console_closed).in_set(EditorSet),
+            )
+            .add_console_command::<UndoCommand, _>(undo_command.in_set(EditorSet))
+            .add_console_command::<RedoCommand, _>(redo_command.in_set(EditorSet));
+    }
+}
+
+// oldest edits are dropped past this
+const HISTORY_LIMIT: usize = 200;
+
+// a single undoable change to the chart, only holding what actually changed
+pub(crate) struct ChartEdit {
+    pub label: &'static str,
+    removed: Vec<NoteId>,
+    added: Vec<NoteId>,
+    timing: Option<(Vec<TimingPoint>, Vec<TimingPoint>)>,
+    // added notes that landed on a note already in the chart and were merged into it
+    merged: usize,
+}
+
+impl ChartEdit {
+    // removes and adds notes, notes already in the chart aren't added twice
+    pub fn notes(
+        label: &'static str,
+        beatmap: &Beatmap,
+        remove: &[NoteId],
+        add: &[NoteId],
+    ) -> Self {
+        // each removed note takes one copy out of the chart, so undoing puts back
+        // exactly as many copies as there were
+        let mut left = beatmap.notes.clone();
+        let mut removed = vec![];
+        for note in remove {
+            if let Some(index) = left.iter().position(|n| n == note) {
+                left.swap_remove(index);
+                removed.push(note.clone());
+            }
+        }
+
+        let mut added: Vec<NoteId> = vec![];
+        let mut merged = 0;
+        for note in add {
+            if left.contains(note) || added.contains(note) {
+                merged += 1;
+            } else {
+                added.push(note.clone());
+            }
+        }
+
+        ChartEdit {
+            label,
+            removed,
+            added,
+            timing: None,
+            merged,
+        }
+    }
+
+    pub fn timing(label: &'static str, beatmap: &Beatmap, after: Vec<TimingPoint>) -> Self {
+        ChartEdit {
+            label,
+            removed: vec![],
+            added: vec![],
+            timing: Some((beatmap.timing_points.clone(), after)),
+            merged: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty() && self.timing.is_none()
+    }
+
+    fn apply(&self, beatmap: &mut Beatmap) {
+        replace_notes(beatmap, &self.removed, &self.added);
+        if let Some((_, after)) = &self.timing {
+            beatmap.timing_points = after.clone();
+        }
+    }
+
+    fn revert(&self, beatmap: &mut Beatmap) {
+        replace_notes(beatmap, &self.added, &self.removed);
+        if let Some((before, _)) = &self.timing {
+            beatmap.timing_points = before.clone();
+        }
+    }
+}
+
+// notes are kept sorted so spawning in play mode still works
+fn replace_notes(beatmap: &mut Beatmap, remove: &[NoteId], add: &[NoteId]) {
+    for note in remove {
+        if let Some(index) = beatmap.notes.iter().position(|n| n == note) {
+            beatmap.notes.remove(index);
+        }
+    }
+    for note in add {
+        let index = beatmap.notes.partition_point(|n| n.timing <= note.timing);
+        beatmap.notes.insert(index, note.clone());
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct EditHistory {
+    undo: VecDeque<ChartEdit>,
+    redo: Vec<ChartEdit>,
+    // set by any edit and cleared by `save`
+    pub dirty: bool,
+}
+
+impl EditHistory {
+    // returns how many added notes were merged into ones already in the chart
+    pub fn apply(&mut self, beatmap: &mut Beatmap, edit: ChartEdit) -> usize {
+        let merged = edit.merged;
+        if edit.is_empty() {
+            return merged;
+        }
+
+        edit.apply(beatmap);
+        self.undo.push_back(edit);
+        if self.undo.len() > HISTORY_LIMIT {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+        self.dirty = true;
+        merged
+    }
+
+    pub fn undo(&mut self, beatmap: &mut Beatmap) -> Option<&'static str> {
+        let edit = self.undo.pop_back()?;
+        edit.revert(beatmap);
+        let label = edit.label;
+        self.redo.push(edit);
+        self.dirty = true;
+        Some(label)
+    }
+
+    pub fn redo(&mut self, beatmap: &mut Beatmap) -> Option<&'static str> {
+        let edit = self.redo.pop()?;
+        edit.apply(beatmap);
+        let label = edit.label;
+        self.undo.push_back(edit);
+        self.dirty = true;
+        Some(label)
+    }
+
+    // a different chart was loaded, the old edits don't apply to it
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.dirty = false;
+    }
+}
+
+// whether switching away from the current state would throw away unsaved edits
+pub(crate) fn leaving_unsaved(state: &State<ApplicationState>, history: &EditHistory) -> bool {
+    state.get() == &ApplicationState::Editor && history.dirty
+}
+
+// ctrl + z undoes, ctrl + y or ctrl + shift + z redoes
+fn history_hotkeys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+) {
+    let ctrl = keys.pressed(KeyCode::ControlLeft) || keys.pressed(KeyCode::ControlRight);
+    let shift = keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight);
+    if !ctrl {
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::KeyZ) && !shift {
+        history.undo(&mut beatmap);
+    } else if keys.just_pressed(KeyCode::KeyY) || (keys.just_pressed(KeyCode::KeyZ) && shift) {
+        history.redo(&mut beatmap);
+    }
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "undo")]
+struct UndoCommand;
+
+fn undo_command(
+    mut log: ConsoleCommand<UndoCommand>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+) {
+    if let Some(Ok(UndoCommand)) = log.take() {
+        match history.undo(&mut beatmap) {
+            Some(label) => log.reply(format!("Undid {}", label)),
+            None => log.reply("Nothing to undo"),
+        }
+    }
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "redo")]
+struct RedoCommand;
+
+fn redo_command(
+    mut log: ConsoleCommand<RedoCommand>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+) {
+    if let Some(Ok(RedoCommand)) = log.take() {
+        match history.redo(&mut beatmap) {
+            Some(label) => log.reply(format!("Redid {}", label)),
+            None => log.reply("Nothing to redo"),
+        }
+    }
+}
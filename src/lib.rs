@@ -1,23 +1,38 @@
 use bevy::{prelude::*, time::Stopwatch};
 use bevy_console::{AddConsoleCommand, ConsoleCommand, ConsolePlugin};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 use editor::{EditorPlugin, EditorSet};
+use history::{leaving_unsaved, EditHistory, HistoryPlugin};
+use loading::{LoadingPlugin, LoadingTarget};
+use metronome::MetronomePlugin;
 use note::{NotePlugin, NoteSet};
 use player::{Pause, PlayerPlugin, PlayerSet};
+use selection::SelectionPlugin;
+use song::SongPlugin;
+use timeline::TimelinePlugin;
 use ui::{UiPlugin, UiSet};
+use waveform::WaveformPlugin;
 
+mod beatmap;
 mod editor;
+mod history;
+mod loading;
+mod metronome;
 mod note;
 mod player;
+mod selection;
+mod song;
+mod timeline;
 mod ui;
+mod waveform;
 
 pub struct MachitanPlugin;
 
 impl Plugin for MachitanPlugin {
     fn build(&self, app: &mut App) {
         // state setup
-        app.insert_state(ApplicationState::Menu)
+        app.insert_state(ApplicationState::Loading)
             .init_state::<ModeState>()
             .init_state::<PauseState>();
 
@@ -33,10 +48,10 @@ impl Plugin for MachitanPlugin {
         );
         app.configure_sets(
             Update,
-            PlayerSet
-                .run_if(in_state(ApplicationState::Menu))
-                .run_if(in_state(ApplicationState::InGame))
-                .run_if(in_state(ApplicationState::Editor)),
+            (
+                PlayerSet.run_if(in_state(ApplicationState::InGame)),
+                EditorSet.run_if(in_state(ApplicationState::Editor)),
+            ),
         );
         app.configure_sets(
             FixedUpdate,
@@ -44,21 +59,30 @@ impl Plugin for MachitanPlugin {
                 NoteSet
                     .run_if(in_state(ApplicationState::InGame))
                     .run_if(in_state(PauseState::Unpaused)),
-                EditorSet.run_if(in_state(ApplicationState::Editor)),
                 UiSet.run_if(in_state(ApplicationState::Menu)),
             ),
         );
 
         // resources
-        app.insert_resource(FramesCount { count: 0 })
-            .insert_resource(Time::<Fixed>::from_hz(60.0));
+        app.insert_resource(FramesCount {
+            count: 0,
+            lead_in: 0,
+        })
+        .insert_resource(Time::<Fixed>::from_hz(60.0));
 
         // plugins
         app.add_plugins((
+            LoadingPlugin,
             PlayerPlugin,
             ConsolePlugin,
             NotePlugin,
             EditorPlugin,
+            HistoryPlugin,
+            MetronomePlugin,
+            TimelinePlugin,
+            SelectionPlugin,
+            WaveformPlugin,
+            SongPlugin,
             UiPlugin,
         ));
 
@@ -69,14 +93,23 @@ impl Plugin for MachitanPlugin {
         );
 
         // console comands
-        app.add_console_command::<EchoCommand, _>(echo_command);
+        app.add_console_command::<EchoCommand, _>(echo_command)
+            .add_console_command::<ModeCommand, _>(mode_command);
     }
 }
 
-// global framecount resource
+// global framecount resource, the chart's frame 0 comes lead_in frames after the clock starts
 #[derive(Resource)]
 pub struct FramesCount {
     pub count: usize,
+    pub lead_in: usize,
+}
+
+impl FramesCount {
+    // position in the chart, negative while the lead-in plays
+    pub fn song_time(&self) -> isize {
+        self.count as isize - self.lead_in as isize
+    }
 }
 
 fn update_framecount(time: Res<Time>, mut frame_count: ResMut<FramesCount>) {
@@ -97,6 +130,51 @@ fn echo_command(mut log: ConsoleCommand<EchoCommand>) {
     }
 }
 
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "mode")]
+struct ModeCommand {
+    mode: Mode,
+    // leave the editor even with unsaved edits
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Clone, ValueEnum)]
+enum Mode {
+    Editor,
+    Play,
+    Menu,
+}
+
+fn mode_command(
+    mut log: ConsoleCommand<ModeCommand>,
+    mut target: ResMut<LoadingTarget>,
+    state: Res<State<ApplicationState>>,
+    history: Res<EditHistory>,
+    mut next_state: ResMut<NextState<ApplicationState>>,
+) {
+    if let Some(Ok(ModeCommand { mode, force })) = log.take() {
+        if !force && leaving_unsaved(&state, &history) {
+            log.reply("The chart has unsaved changes, `save` it first or use --force");
+            return;
+        }
+
+        // the editor and game both need the selected chart loaded first
+        match mode {
+            Mode::Editor => {
+                target.0 = ApplicationState::Editor;
+                next_state.set(ApplicationState::Loading);
+            }
+            Mode::Play => {
+                target.0 = ApplicationState::InGame;
+                next_state.set(ApplicationState::Loading);
+            }
+            Mode::Menu => next_state.set(ApplicationState::Menu),
+        }
+        log.reply("Switching modes");
+    }
+}
+
 // game states
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
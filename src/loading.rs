@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{RecursiveDependencyLoadState, UntypedAssetId},
+    prelude::*,
+};
+
+use machitan_format::NoteLane;
+
+use crate::{note::Beatmap, ApplicationState};
+
+pub struct LoadingPlugin;
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LoadingSet;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameAssets>()
+            .init_resource::<SelectedChart>()
+            .insert_resource(LoadingTarget(ApplicationState::Menu))
+            .add_systems(
+                OnEnter(ApplicationState::Loading),
+                (queue_assets, loading_screen_setup).chain(),
+            )
+            .add_systems(
+                Update,
+                check_loading
+                    .in_set(LoadingSet)
+                    .run_if(in_state(ApplicationState::Loading)),
+            )
+            .add_systems(OnExit(ApplicationState::Loading), clear_loading_screen);
+    }
+}
+
+// asset paths for the default skin
+const PLAYER_SPRITE: &str = "machitan.png";
+const NOTE_SPRITE: &str = "sq_note.png";
+const FRAME_SPRITE: &str = "note_frame.png";
+const GANBARU_SOUND: &str = "ganbaru.ogg";
+const HITSOUNDS: [&str; 8] = [
+    "mun1.ogg", "mun2.ogg", "mun3.ogg", "mun4.ogg", "mun5.ogg", "mun6.ogg", "mun7.ogg", "mun8.ogg",
+];
+
+// every handle needed by the menu and the selected chart, filled in while loading
+#[derive(Resource, Default)]
+pub struct GameAssets {
+    pub player: Handle<Image>,
+    pub note: Handle<Image>,
+    pub note_frame: Handle<Image>,
+    pub ganbaru: Handle<AudioSource>,
+    pub hitsounds: Vec<Handle<AudioSource>>,
+    pub chart: Option<Handle<Beatmap>>,
+    pub song: Option<Handle<AudioSource>>,
+    // the chart's keysound samples by path
+    pub keysounds: HashMap<String, Handle<AudioSource>>,
+}
+
+impl GameAssets {
+    pub fn hitsound(&self, lane: &NoteLane) -> Handle<AudioSource> {
+        let index = lane.index();
+        self.hitsounds[index].clone()
+    }
+
+    fn ids(&self) -> Vec<UntypedAssetId> {
+        let mut ids: Vec<UntypedAssetId> = vec![
+            self.player.id().untyped(),
+            self.note.id().untyped(),
+            self.note_frame.id().untyped(),
+            self.ganbaru.id().untyped(),
+        ];
+        ids.extend(self.hitsounds.iter().map(|h| h.id().untyped()));
+        if let Some(chart) = &self.chart {
+            ids.push(chart.id().untyped());
+        }
+        if let Some(song) = &self.song {
+            ids.push(song.id().untyped());
+        }
+        ids.extend(self.keysounds.values().map(|h| h.id().untyped()));
+        ids
+    }
+}
+
+// chart chosen to be played next, loaded with its audio alongside the rest of the assets
+#[derive(Resource, Default)]
+pub struct SelectedChart {
+    pub chart: Option<String>,
+    pub song: Option<String>,
+    pub keysounds: Vec<String>,
+}
+
+// state to enter once everything has finished loading
+#[derive(Resource)]
+pub struct LoadingTarget(pub ApplicationState);
+
+#[derive(Component)]
+struct LoadingTag;
+
+#[derive(Component)]
+struct LoadingText;
+
+fn queue_assets(
+    server: Res<AssetServer>,
+    selected: Res<SelectedChart>,
+    mut assets: ResMut<GameAssets>,
+) {
+    *assets = GameAssets {
+        player: server.load(PLAYER_SPRITE),
+        note: server.load(NOTE_SPRITE),
+        note_frame: server.load(FRAME_SPRITE),
+        ganbaru: server.load(GANBARU_SOUND),
+        hitsounds: HITSOUNDS.iter().map(|path| server.load(*path)).collect(),
+        chart: selected
+            .chart
+            .as_ref()
+            .map(|path| server.load(path.clone())),
+        song: selected.song.as_ref().map(|path| server.load(path.clone())),
+        keysounds: selected
+            .keysounds
+            .iter()
+            .map(|path| (path.clone(), server.load(path.clone())))
+            .collect(),
+    };
+}
+
+fn loading_screen_setup(mut commands: Commands) {
+    commands
+        .spawn((
+            LoadingTag,
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                LoadingText,
+                TextBundle::from_section(
+                    "Loading...",
+                    TextStyle {
+                        font_size: 40.0,
+                        color: Color::ALICE_BLUE,
+                        ..default()
+                    },
+                ),
+            ));
+        });
+}
+
+fn check_loading(
+    server: Res<AssetServer>,
+    assets: Res<GameAssets>,
+    target: Res<LoadingTarget>,
+    mut text_query: Query<&mut Text, With<LoadingText>>,
+    mut next_state: ResMut<NextState<ApplicationState>>,
+) {
+    let ids = assets.ids();
+    let mut done = 0;
+    for id in ids.iter() {
+        match server.get_recursive_dependency_load_state(*id) {
+            Some(RecursiveDependencyLoadState::Loaded) => done += 1,
+            Some(RecursiveDependencyLoadState::Failed) => {
+                // don't hang forever on a missing file, just carry on without it
+                warn!("failed to load {:?}", server.get_path(*id));
+                done += 1;
+            }
+            _ => {}
+        }
+    }
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Loading... {}/{}", done, ids.len());
+    }
+
+    if done == ids.len() {
+        next_state.set(target.0.clone());
+    }
+}
+
+fn clear_loading_screen(mut commands: Commands, query: Query<Entity, With<LoadingTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
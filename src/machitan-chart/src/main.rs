@@ -0,0 +1,577 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use machitan_format::{
+    export::osu::{export_osu, write_osz},
+    file::{pretty_chart, pretty_timed_chart, read_chart, read_timed_chart},
+    import::{
+        bms::import_bms,
+        midi::{import_midi, pitch_ranges},
+        osu::import_osu,
+        sm::import_sm,
+        Import,
+    },
+    timing::{beat_frames, quantise_note, timing_point_at, FRAMES_PER_SECOND},
+    validate::{report, validate, Severity},
+    Chart, NoteLane, LANES,
+};
+
+// chart tools that don't need the game window, for scripts and CI
+#[derive(Parser)]
+#[command(name = "machitan-chart")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    // fails when any chart has errors
+    Validate {
+        #[arg(required = true)]
+        charts: Vec<PathBuf>,
+    },
+    // rewrites a chart sorted with one table per note
+    PrettyPrint {
+        chart: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    // converts a chart between frames and seconds with --to, or a single time with
+    // --frames or --seconds, along with the beat it lands on when given a chart
+    Convert {
+        #[arg(
+            long,
+            conflicts_with = "seconds",
+            required_unless_present_any = ["seconds", "to"]
+        )]
+        frames: Option<f64>,
+        #[arg(long, conflicts_with = "to")]
+        seconds: Option<f64>,
+        #[arg(long)]
+        chart: Option<PathBuf>,
+        // what the chart's times should be in, it's read in the other unit
+        #[arg(long, value_enum, requires = "chart", conflicts_with = "frames")]
+        to: Option<Unit>,
+        #[arg(short, long, requires = "to")]
+        output: Option<PathBuf>,
+    },
+    // snaps every note to the chart's timing points
+    Quantise {
+        chart: PathBuf,
+        #[arg(long, default_value_t = 16)]
+        divisor: u32,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    // combines the notes of several charts, metadata and timing come from the first
+    Merge {
+        #[arg(required = true, num_args = 2..)]
+        charts: Vec<PathBuf>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    Stats {
+        chart: PathBuf,
+    },
+    // converts a chart from another game or a MIDI file, picked by its extension
+    // (.osu, .sm, .ssc, .bms, .bme or .mid)
+    Import {
+        file: PathBuf,
+        #[command(flatten)]
+        options: ImportOptions,
+        // folder under assets the song and keysounds get copied to
+        #[arg(long)]
+        samples: Option<String>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    // writes an osu!mania beatmap, packed with its audio when the output ends in .osz
+    Export {
+        chart: PathBuf,
+        // lane keys that become the beatmap's columns, the span of lanes with notes when left out
+        #[arg(long)]
+        lanes: Option<String>,
+        // folder the chart's song and keysound paths are relative to
+        #[arg(long, default_value = "assets")]
+        assets: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Unit {
+    // 60 fps frames, what the game plays
+    Frames,
+    // seconds, see machitan_format::timed
+    Seconds,
+}
+
+#[derive(Args)]
+struct ImportOptions {
+    // lane keys for the chart's columns from left to right, like `dfjk`
+    #[arg(long)]
+    lanes: Option<String>,
+    // which of the file's charts to import by difficulty, the hardest when left out
+    #[arg(long)]
+    difficulty: Option<String>,
+    // MIDI track to take the notes from, the one with the most notes when left out
+    #[arg(long)]
+    track: Option<usize>,
+    // MIDI pitches or pitch ranges for the lanes from left to right, like `36,38,42-46`
+    #[arg(long)]
+    pitches: Option<String>,
+}
+
+fn load(path: &Path) -> Result<Chart, String> {
+    read_chart(path).map_err(|e| e.to_string())
+}
+
+fn output(chart: &Chart, path: Option<PathBuf>) -> Result<(), String> {
+    output_text(pretty_chart(chart).map_err(|e| e.to_string())?, path)
+}
+
+// prints to stdout unless given a file
+fn output_text(text: String, path: Option<PathBuf>) -> Result<(), String> {
+    match path {
+        Some(path) => fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+// the chart rewritten in the given unit, read from a chart in the other one
+fn convert_chart(path: &Path, unit: Unit) -> Result<String, String> {
+    let text = match unit {
+        Unit::Seconds => pretty_timed_chart(&load(path)?),
+        Unit::Frames => {
+            let timed = read_timed_chart(path).map_err(|e| e.to_string())?;
+            pretty_chart(&Chart::from(&timed))
+        }
+    };
+    text.map_err(|e| e.to_string())
+}
+
+// snaps every note to the chart's timing points, returning how many moved and how
+// many snapped onto another note and were merged into it
+fn quantise_chart(chart: &mut Chart, divisor: u32) -> (usize, usize) {
+    let mut moved = 0;
+    for note in chart.notes.iter_mut() {
+        let snapped = quantise_note(&chart.timing_points, note, divisor);
+        if snapped != *note {
+            moved += 1;
+        }
+        *note = snapped;
+    }
+    let count = chart.notes.len();
+    dedup_notes(chart);
+    (moved, count - chart.notes.len())
+}
+
+// the first chart with the notes and keysounds of the others added, metadata and timing
+// come from it
+fn merge_charts(charts: Vec<Chart>) -> Option<Chart> {
+    let mut charts = charts.into_iter();
+    let mut merged = charts.next()?;
+    for chart in charts {
+        merged.notes.extend(chart.notes);
+        merged.keysounds.extend(chart.keysounds);
+    }
+    merged.keysounds.sort_by_key(|keysound| keysound.timing);
+    dedup_notes(&mut merged);
+    Some(merged)
+}
+
+// notes on the same frame and lane become one, a hold wins over a tap and the
+// longest hold over shorter ones like when importing
+fn dedup_notes(chart: &mut Chart) {
+    chart
+        .notes
+        .sort_by_key(|note| (note.timing, note.lane.index(), Reverse(note.end)));
+    chart
+        .notes
+        .dedup_by(|a, b| a.timing == b.timing && a.lane == b.lane);
+}
+
+fn parse_lanes(lanes: Option<String>) -> Result<Option<Vec<NoteLane>>, String> {
+    lanes
+        .map(|lanes| {
+            lanes
+                .chars()
+                .map(|c| NoteLane::from_char(c).ok_or(format!("{} isn't a lane key", c)))
+                .collect()
+        })
+        .transpose()
+}
+
+fn import(path: &Path, options: ImportOptions) -> Result<Import, String> {
+    let lanes = parse_lanes(options.lanes)?;
+    let lanes = lanes.as_deref();
+    let pitches = options.pitches.as_deref().map(pitch_ranges).transpose()?;
+
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let source = String::from_utf8_lossy(&bytes);
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let import = match extension.to_lowercase().as_str() {
+        "osu" => import_osu(&source, lanes),
+        "sm" | "ssc" => import_sm(&source, options.difficulty.as_deref(), lanes),
+        "bms" | "bme" | "bml" => import_bms(&bytes, lanes),
+        "mid" | "midi" => import_midi(&bytes, options.track, pitches.as_deref(), lanes),
+        _ => {
+            return Err(format!(
+                "{} isn't a chart format machitan can import",
+                extension
+            ))
+        }
+    };
+    import.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn run(command: Command) -> Result<bool, String> {
+    match command {
+        Command::Validate { charts } => {
+            let mut valid = true;
+            for path in charts {
+                println!("{}", path.display());
+                // a chart that can't be read fails the run, the rest still get checked
+                let issues = match load(&path) {
+                    Ok(chart) => validate(&chart),
+                    Err(e) => {
+                        println!("  error: {}", e);
+                        valid = false;
+                        continue;
+                    }
+                };
+                for line in report(&issues) {
+                    println!("  {}", line);
+                }
+                valid &= !issues.iter().any(|issue| issue.severity == Severity::Error);
+            }
+            Ok(valid)
+        }
+        Command::PrettyPrint { chart, output: out } => {
+            output(&load(&chart)?, out)?;
+            Ok(true)
+        }
+        Command::Convert {
+            chart,
+            to: Some(unit),
+            output: out,
+            ..
+        } => {
+            let path = chart.ok_or("--to needs a --chart to convert")?;
+            output_text(convert_chart(&path, unit)?, out)?;
+            Ok(true)
+        }
+        Command::Convert {
+            frames,
+            seconds,
+            chart,
+            ..
+        } => {
+            let frames = frames.unwrap_or_else(|| seconds.unwrap_or(0.0) * FRAMES_PER_SECOND);
+            println!("frame {} is {:.3}s", frames, frames / FRAMES_PER_SECOND);
+            if let Some(chart) = chart {
+                let chart = load(&chart)?;
+                let point = timing_point_at(&chart.timing_points, frames);
+                let beat = (frames - point.timing) / beat_frames(point.bpm);
+                println!(
+                    "beat {:.2} of the {} bpm section starting at frame {}",
+                    beat, point.bpm, point.timing
+                );
+            }
+            Ok(true)
+        }
+        Command::Quantise {
+            chart,
+            divisor,
+            output: out,
+        } => {
+            let mut chart = load(&chart)?;
+            let divisor = divisor.max(1);
+            let (moved, merged) = quantise_chart(&mut chart, divisor);
+            eprintln!(
+                "moved {} notes to 1/{}, merged {} duplicates",
+                moved, divisor, merged
+            );
+            output(&chart, out)?;
+            Ok(true)
+        }
+        Command::Merge {
+            charts,
+            output: out,
+        } => {
+            let charts = charts
+                .iter()
+                .map(|path| load(path))
+                .collect::<Result<Vec<_>, _>>()?;
+            let merged = merge_charts(charts).ok_or("no charts to merge")?;
+            output(&merged, out)?;
+            Ok(true)
+        }
+        Command::Stats { chart } => {
+            let chart = load(&chart)?;
+            print_stats(&chart);
+            Ok(true)
+        }
+        Command::Import {
+            file,
+            options,
+            samples,
+            output: out,
+        } => {
+            let mut imported = import(&file, options)?;
+            if let Some(folder) = samples {
+                imported.in_folder(&folder);
+            }
+            let Import { chart, warnings } = imported;
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
+            for line in report(&validate(&chart)) {
+                eprintln!("{}", line);
+            }
+            output(&chart, out)?;
+            Ok(true)
+        }
+        Command::Export {
+            chart,
+            lanes,
+            assets,
+            output: out,
+        } => {
+            let lanes = parse_lanes(lanes)?;
+            let export = export_osu(&load(&chart)?, lanes.as_deref()).map_err(|e| e.to_string())?;
+            for warning in export.warnings.iter() {
+                eprintln!("{}", warning);
+            }
+            match out {
+                Some(path)
+                    if path
+                        .extension()
+                        .is_some_and(|e| e.eq_ignore_ascii_case("osz")) =>
+                {
+                    write_osz(&path, &export, |audio| assets.join(audio))
+                        .map_err(|e| e.to_string())?
+                }
+                Some(path) => fs::write(&path, &export.text)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+                None => print!("{}", export.text),
+            }
+            Ok(true)
+        }
+    }
+}
+
+fn print_stats(chart: &Chart) {
+    let metadata = &chart.metadata;
+    println!(
+        "{} - {} charted by {}",
+        metadata.artist, metadata.title, metadata.charter
+    );
+    println!("{} notes", chart.notes.len());
+    let holds = chart.notes.iter().filter(|note| note.end.is_some()).count();
+    if holds > 0 {
+        println!("{} of them holds", holds);
+    }
+    if !chart.keysounds.is_empty() {
+        println!("{} keysounds", chart.keysounds.len());
+    }
+
+    let mut lanes = HashMap::new();
+    for note in chart.notes.iter() {
+        *lanes.entry(note.lane).or_insert(0) += 1;
+    }
+    for lane in LANES.iter() {
+        println!("  {:?}: {}", lane, lanes.get(lane).unwrap_or(&0));
+    }
+
+    let mut timings: Vec<usize> = chart.notes.iter().map(|note| note.timing).collect();
+    timings.sort();
+    if let (Some(first), Some(last)) = (timings.first(), timings.last()) {
+        let length = (last - first) as f64 / FRAMES_PER_SECOND;
+        println!(
+            "{:.1}s from the first note to the last, {:.2} notes per second",
+            length,
+            if length > 0.0 {
+                timings.len() as f64 / length
+            } else {
+                0.0
+            }
+        );
+
+        // busiest second of the chart
+        let window = FRAMES_PER_SECOND as usize;
+        let peak = (0..timings.len())
+            .map(|start| {
+                timings[start..].partition_point(|timing| *timing < timings[start] + window)
+            })
+            .max()
+            .unwrap_or(0);
+        println!("peak of {} notes in one second", peak);
+    }
+
+    let bpms = chart.timing_points.iter().map(|point| point.bpm);
+    match (bpms.clone().reduce(f64::min), bpms.reduce(f64::max)) {
+        (Some(min), Some(max)) if min == max => println!("{} bpm", min),
+        (Some(min), Some(max)) => println!(
+            "{} to {} bpm over {} timing points",
+            min,
+            max,
+            chart.timing_points.len()
+        ),
+        _ => println!("no timing points"),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("machitan-chart: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use machitan_format::{
+        chart::{
+            Keysound, NoteId,
+            NoteLane::{self, *},
+        },
+        timing::{TimingPoint, DEFAULT_TIMING_POINT},
+    };
+
+    use super::*;
+
+    fn note(timing: usize, lane: NoteLane) -> NoteId {
+        NoteId::tap(timing, lane)
+    }
+
+    fn chart(notes: Vec<NoteId>) -> Chart {
+        Chart {
+            timing_points: vec![DEFAULT_TIMING_POINT],
+            notes,
+            ..Default::default()
+        }
+    }
+
+    // a file under the temp folder for the commands that read from disk
+    fn temp_file(name: &str, text: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("machitan-chart-{}-{}", process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn merge_keeps_one_of_each_note() {
+        let left = chart(vec![note(30, LaneD), note(60, LaneF)]);
+        let mut right = chart(vec![note(60, LaneF), note(60, LaneJ)]);
+        right.timing_points[0].bpm = 180.0;
+
+        let merged = merge_charts(vec![left, right]).unwrap();
+        assert_eq!(
+            merged.notes,
+            [note(30, LaneD), note(60, LaneF), note(60, LaneJ)]
+        );
+        // timing comes from the first chart
+        assert_eq!(merged.timing_points[0].bpm, 120.0);
+    }
+
+    #[test]
+    fn merge_keeps_the_longest_hold_on_a_frame() {
+        let hold = |end| NoteId {
+            end: Some(end),
+            ..note(30, LaneD)
+        };
+        let left = chart(vec![note(30, LaneD), hold(60)]);
+        let right = chart(vec![hold(90), note(30, LaneF)]);
+
+        let merged = merge_charts(vec![left, right]).unwrap();
+        assert_eq!(merged.notes, [hold(90), note(30, LaneF)]);
+    }
+
+    #[test]
+    fn merge_adds_keysounds_in_order() {
+        let keysound = |timing, sample: &str| Keysound {
+            timing,
+            lane: None,
+            sample: sample.into(),
+        };
+        let mut left = chart(vec![]);
+        left.keysounds = vec![keysound(60, "snare.wav")];
+        let mut right = chart(vec![]);
+        right.keysounds = vec![keysound(30, "kick.wav")];
+
+        let merged = merge_charts(vec![left, right]).unwrap();
+        let samples: Vec<&str> = merged
+            .keysounds
+            .iter()
+            .map(|keysound| keysound.sample.as_str())
+            .collect();
+        assert_eq!(samples, ["kick.wav", "snare.wav"]);
+    }
+
+    #[test]
+    fn quantise_snaps_and_merges_notes() {
+        let mut chart = chart(vec![note(29, LaneD), note(31, LaneD), note(44, LaneF)]);
+        // 1/4 snaps to every 30 frame beat at 120 bpm
+        assert_eq!(quantise_chart(&mut chart, 4), (3, 1));
+        assert_eq!(chart.notes, [note(30, LaneD), note(30, LaneF)]);
+    }
+
+    #[test]
+    fn quantise_lets_a_hold_win_over_a_tap() {
+        let hold = NoteId {
+            end: Some(88),
+            ..note(32, LaneD)
+        };
+        let mut chart = chart(vec![note(29, LaneD), hold]);
+        assert_eq!(quantise_chart(&mut chart, 4), (2, 1));
+        assert_eq!(
+            chart.notes,
+            [NoteId {
+                end: Some(90),
+                ..note(30, LaneD)
+            }]
+        );
+    }
+
+    #[test]
+    fn converts_charts_to_seconds_and_back() {
+        let mut chart = chart(vec![note(30, LaneD), note(75, LaneK)]);
+        chart.timing_points = vec![TimingPoint {
+            timing: 12.0,
+            ..DEFAULT_TIMING_POINT
+        }];
+        let frames = temp_file("frames.toml", &pretty_chart(&chart).unwrap());
+
+        let seconds = convert_chart(&frames, Unit::Seconds).unwrap();
+        assert!(seconds.contains("time = 1.25"), "{}", seconds);
+        let seconds = temp_file("seconds.toml", &seconds);
+
+        let back = convert_chart(&seconds, Unit::Frames).unwrap();
+        assert_eq!(back, pretty_chart(&chart).unwrap());
+        fs::remove_file(frames).unwrap();
+        fs::remove_file(seconds).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+use crate::timing::TimingPoint;
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct Chart {
+    #[serde(default)]
+    pub metadata: ChartMetadata,
+    #[serde(default)]
+    pub timing_points: Vec<TimingPoint>,
+    pub notes: Vec<NoteId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keysounds: Vec<Keysound>,
+}
+
+impl Chart {
+    // sample the note plays instead of the lane hitsound
+    pub fn keysound(&self, note: &NoteId) -> Option<&str> {
+        self.keysounds
+            .iter()
+            .find(|keysound| keysound.timing == note.timing && keysound.lane == Some(note.lane))
+            .map(|keysound| keysound.sample.as_str())
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct ChartMetadata {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub artist: String,
+    #[serde(default)]
+    pub charter: String,
+    // audio file, relative to the game's assets folder
+    #[serde(default)]
+    pub song: String,
+}
+
+// a sample played on a note's hit, or in the background when it has no lane
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Keysound {
+    pub timing: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lane: Option<NoteLane>,
+    // audio file, relative to the game's assets folder
+    pub sample: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Deserialize, Serialize, Debug)]
+pub struct NoteId {
+    // frame on the 60 fps song clock the note should be hit on
+    pub timing: usize,
+    pub lane: NoteLane,
+    // frame a hold is let go on, taps don't have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<usize>,
+}
+
+impl NoteId {
+    pub fn tap(timing: usize, lane: NoteLane) -> Self {
+        NoteId {
+            timing,
+            lane,
+            end: None,
+        }
+    }
+
+    // frames a hold lasts, 0 for taps
+    pub fn length(&self) -> usize {
+        self.end.map_or(0, |end| end.saturating_sub(self.timing))
+    }
+
+    // the last frame the note still needs the key, its timing for taps
+    pub fn last_frame(&self) -> usize {
+        self.end.unwrap_or(self.timing).max(self.timing)
+    }
+
+    // the same note starting somewhere else, holds keep their length
+    pub fn moved(&self, timing: usize, lane: NoteLane) -> Self {
+        NoteId {
+            timing,
+            lane,
+            end: self.end.map(|_| timing + self.length()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Copy, Serialize, Deserialize, Debug)]
+pub enum NoteLane {
+    LaneA,
+    LaneS,
+    LaneD,
+    LaneF,
+    LaneJ,
+    LaneK,
+    LaneL,
+    LaneSemicolon,
+}
+
+// left to right
+pub const LANES: [NoteLane; 8] = [
+    NoteLane::LaneA,
+    NoteLane::LaneS,
+    NoteLane::LaneD,
+    NoteLane::LaneF,
+    NoteLane::LaneJ,
+    NoteLane::LaneK,
+    NoteLane::LaneL,
+    NoteLane::LaneSemicolon,
+];
+
+// lanes are named after the keys they're played with
+const LANE_CHARS: [char; 8] = ['a', 's', 'd', 'f', 'j', 'k', 'l', ';'];
+
+impl NoteLane {
+    pub fn index(&self) -> usize {
+        LANES.iter().position(|lane| lane == self).unwrap()
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        let index = LANE_CHARS.iter().position(|k| *k == c)?;
+        Some(LANES[index])
+    }
+}
+
+// frames a note takes to scroll from the top of the screen to its lane frame
+pub const FRAMES_TO_TIMING: usize = 56;
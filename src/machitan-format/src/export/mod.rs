@@ -0,0 +1,31 @@
+use std::{fmt, io, path::PathBuf};
+
+// converters to other games' chart formats, each one reports what it couldn't carry over
+
+pub mod osu;
+
+pub struct Export {
+    // file name the beatmap goes by in its archive
+    pub name: String,
+    pub text: String,
+    // audio the beatmap plays, as chart paths relative to the assets folder
+    pub audio: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Unsupported(String),
+    Io { path: PathBuf, error: io::Error },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Unsupported(message) => write!(f, "can't export the chart: {}", message),
+            ExportError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
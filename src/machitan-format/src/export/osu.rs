@@ -0,0 +1,368 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use zip::{
+    write::{SimpleFileOptions, ZipWriter},
+    CompressionMethod,
+};
+
+use crate::{
+    chart::{Chart, NoteLane, LANES},
+    timing::{DEFAULT_TIMING_POINT, FRAMES_PER_SECOND},
+    warn_count,
+};
+
+use super::{Export, ExportError};
+
+// osu!mania beatmaps, the .osu text format and .osz archives of a beatmap with its audio
+
+const PLAYFIELD_WIDTH: f64 = 512.0;
+// hit objects are placed on the playfield's middle row, mania only reads their x
+const PLAYFIELD_MIDDLE: u32 = 192;
+const VOLUME: u32 = 100;
+// hit object types
+const NOTE: u32 = 1;
+const HOLD_NOTE: u32 = 128;
+
+fn frames_to_ms(frames: f64) -> f64 {
+    frames * 1000.0 / FRAMES_PER_SECOND
+}
+
+// audio sits next to the beatmap in the archive, so only its file name is kept
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+// characters Windows doesn't allow in file names
+fn clean(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+// machitan charts name their difficulty like imported ones do, `Title [Hard]`
+fn split_title(title: &str) -> (&str, &str) {
+    title
+        .strip_suffix(']')
+        .and_then(|title| title.rsplit_once(" ["))
+        .unwrap_or((title, "machitan"))
+}
+
+// lanes for the beatmap's columns, from the leftmost lane with notes to the rightmost when not given
+fn columns(chart: &Chart, lanes: Option<&[NoteLane]>) -> Result<Vec<NoteLane>, ExportError> {
+    if let Some(lanes) = lanes {
+        if lanes.is_empty() {
+            return Err(ExportError::Unsupported("no lanes given".into()));
+        }
+        // a lane on two columns would only ever get notes in the first one
+        if let Some(lane) = (1..lanes.len())
+            .find(|index| lanes[..*index].contains(&lanes[*index]))
+            .map(|index| lanes[index])
+        {
+            return Err(ExportError::Unsupported(format!(
+                "{:?} is given more than once",
+                lane
+            )));
+        }
+        return Ok(lanes.to_vec());
+    }
+    let used = chart.notes.iter().map(|note| note.lane.index());
+    match (used.clone().min(), used.max()) {
+        (Some(first), Some(last)) => Ok(LANES[first..=last].to_vec()),
+        _ => Err(ExportError::Unsupported("the chart has no notes".into())),
+    }
+}
+
+pub fn export_osu(chart: &Chart, lanes: Option<&[NoteLane]>) -> Result<Export, ExportError> {
+    let columns = columns(chart, lanes)?;
+    let keys = columns.len();
+    let mut warnings = vec![];
+
+    let metadata = &chart.metadata;
+    let (title, version) = split_title(&metadata.title);
+    let audio: Vec<String> = (!metadata.song.is_empty())
+        .then(|| metadata.song.clone())
+        .into_iter()
+        .chain(
+            chart
+                .keysounds
+                .iter()
+                .map(|keysound| keysound.sample.clone()),
+        )
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if audio.is_empty() {
+        warnings.push("the chart has no song or keysounds, so the beatmap has no audio".into());
+    }
+
+    // writing to a String can't fail
+    let mut text = String::from("osu file format v14\n\n[General]\n");
+    writeln!(text, "AudioFilename: {}", file_name(&metadata.song)).ok();
+    text.push_str("AudioLeadIn: 0\nPreviewTime: -1\nCountdown: 0\nSampleSet: Soft\n");
+    text.push_str("Mode: 3\n\n[Metadata]\n");
+    for (key, value) in [
+        ("Title", title),
+        ("TitleUnicode", title),
+        ("Artist", &metadata.artist),
+        ("ArtistUnicode", &metadata.artist),
+        ("Creator", &metadata.charter),
+        ("Version", version),
+    ] {
+        writeln!(text, "{}:{}", key, value).ok();
+    }
+    text.push_str("Source:\nTags:machitan\nBeatmapID:0\nBeatmapSetID:-1\n\n[Difficulty]\n");
+    writeln!(
+        text,
+        "HPDrainRate:8\nCircleSize:{}\nOverallDifficulty:8",
+        keys
+    )
+    .ok();
+    text.push_str("ApproachRate:5\nSliderMultiplier:1.4\nSliderTickRate:1\n\n[Events]\n");
+
+    // keysounds without a lane play in the background as storyboard samples
+    for keysound in chart
+        .keysounds
+        .iter()
+        .filter(|keysound| keysound.lane.is_none())
+    {
+        writeln!(
+            text,
+            "Sample,{},0,\"{}\",{}",
+            frames_to_ms(keysound.timing as f64).round(),
+            file_name(&keysound.sample),
+            VOLUME
+        )
+        .ok();
+    }
+
+    text.push_str("\n[TimingPoints]\n");
+    let default_points = [DEFAULT_TIMING_POINT];
+    let points = if chart.timing_points.is_empty() {
+        warnings.push("the chart has no timing points, the beatmap is timed at 120 bpm".into());
+        &default_points[..]
+    } else {
+        &chart.timing_points[..]
+    };
+    for point in points {
+        writeln!(
+            text,
+            "{},{},{},2,0,{},1,0",
+            frames_to_ms(point.timing).round(),
+            60_000.0 / point.bpm,
+            point.beats_per_measure,
+            VOLUME
+        )
+        .ok();
+    }
+    warn_count(
+        &mut warnings,
+        points.iter().filter(|point| point.beat_unit != 4).count(),
+        "timing points have a beat unit other than 4, osu! only keeps their beat count",
+    );
+
+    // holds put their end time in front of the hit sample
+    text.push_str("\n[HitObjects]\n");
+    let mut notes: Vec<_> = chart.notes.iter().collect();
+    notes.sort_by_key(|note| (note.timing, note.lane.index()));
+    let mut outside = 0;
+    for note in notes {
+        let Some(column) = columns.iter().position(|lane| *lane == note.lane) else {
+            outside += 1;
+            continue;
+        };
+        let x = ((column as f64 + 0.5) * PLAYFIELD_WIDTH / keys as f64).floor();
+        let sample = chart.keysound(note).map_or("", file_name);
+        let (kind, end) = match note.end {
+            Some(end) => (HOLD_NOTE, format!("{}:", frames_to_ms(end as f64).round())),
+            None => (NOTE, String::new()),
+        };
+        writeln!(
+            text,
+            "{},{},{},{},0,{}0:0:0:{}:{}",
+            x,
+            PLAYFIELD_MIDDLE,
+            frames_to_ms(note.timing as f64).round(),
+            kind,
+            end,
+            if sample.is_empty() { 0 } else { VOLUME },
+            sample
+        )
+        .ok();
+    }
+    warn_count(
+        &mut warnings,
+        outside,
+        "notes were in lanes outside the exported ones and were left out",
+    );
+
+    let names: BTreeSet<&str> = audio.iter().map(|path| file_name(path)).collect();
+    if names.len() < audio.len() {
+        warnings.push("audio files from different folders share a name, only one is kept".into());
+    }
+
+    Ok(Export {
+        name: clean(&format!(
+            "{} - {} ({}) [{}].osu",
+            metadata.artist, title, metadata.charter, version
+        )),
+        text,
+        audio,
+        warnings,
+    })
+}
+
+// packs the beatmap with its audio, audio_path finds a chart path on disk
+pub fn write_osz(
+    path: &Path,
+    export: &Export,
+    audio_path: impl Fn(&str) -> PathBuf,
+) -> Result<(), ExportError> {
+    let io_error = |path: &Path| {
+        let path = path.to_owned();
+        move |error| ExportError::Io { path, error }
+    };
+    let zip_error = |error: zip::result::ZipError| ExportError::Io {
+        path: path.to_owned(),
+        error: error.into(),
+    };
+
+    // the audio is read first so a missing file doesn't leave half an archive
+    let mut files = vec![];
+    for audio in export.audio.iter() {
+        let name = file_name(audio);
+        if files.iter().any(|(added, _)| *added == name) {
+            continue;
+        }
+        let source = audio_path(audio);
+        files.push((name, fs::read(&source).map_err(io_error(&source))?));
+    }
+
+    let file = fs::File::create(path).map_err(io_error(path))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(export.name.as_str(), options)
+        .map_err(zip_error)?;
+    zip.write_all(export.text.as_bytes())
+        .map_err(io_error(path))?;
+
+    // audio is compressed already
+    let stored = options.compression_method(CompressionMethod::Stored);
+    for (name, bytes) in files {
+        zip.start_file(name, stored).map_err(zip_error)?;
+        zip.write_all(&bytes).map_err(io_error(path))?;
+    }
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chart::{ChartMetadata, Keysound, NoteId, NoteLane::*},
+        import::osu::import_osu,
+    };
+
+    fn chart() -> Chart {
+        Chart {
+            metadata: ChartMetadata {
+                title: "Song [Hard]".into(),
+                artist: "Someone".into(),
+                charter: "Mapper".into(),
+                song: "songs/song.ogg".into(),
+            },
+            timing_points: vec![DEFAULT_TIMING_POINT],
+            notes: vec![
+                NoteId::tap(30, LaneD),
+                NoteId {
+                    timing: 60,
+                    lane: LaneF,
+                    end: Some(90),
+                },
+                NoteId::tap(90, LaneK),
+            ],
+            keysounds: vec![Keysound {
+                timing: 30,
+                lane: Some(LaneD),
+                sample: "songs/kick.wav".into(),
+            }],
+        }
+    }
+
+    #[test]
+    fn writes_notes_holds_and_keysounds() {
+        let export = export_osu(&chart(), None).unwrap();
+        assert_eq!(export.name, "Someone - Song (Mapper) [Hard].osu");
+        assert_eq!(export.audio, ["songs/kick.wav", "songs/song.ogg"]);
+        assert!(export.warnings.is_empty());
+        let text = export.text;
+        assert!(text.contains("AudioFilename: song.ogg\n"));
+        assert!(text.contains("CircleSize:4\n"));
+        assert!(text.contains("Version:Hard\n"));
+        assert!(text.contains("0,500,4,2,0,100,1,0\n"));
+        let objects: Vec<&str> = text
+            .split("[HitObjects]\n")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .collect();
+        assert_eq!(
+            objects,
+            [
+                "64,192,500,1,0,0:0:0:100:kick.wav",
+                "192,192,1000,128,0,1500:0:0:0:0:",
+                "448,192,1500,1,0,0:0:0:0:",
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_back_the_same_chart() {
+        let chart = chart();
+        let import = import_osu(&export_osu(&chart, None).unwrap().text, None).unwrap();
+        assert_eq!(import.chart.notes, chart.notes);
+        assert_eq!(import.chart.timing_points, chart.timing_points);
+        assert_eq!(import.chart.metadata.title, "Song [Hard]");
+    }
+
+    #[test]
+    fn leaves_out_notes_outside_the_given_lanes() {
+        let export = export_osu(&chart(), Some(&[LaneD, LaneF])).unwrap();
+        assert!(export.text.contains("CircleSize:2\n"));
+        assert_eq!(
+            export.warnings,
+            ["1 notes were in lanes outside the exported ones and were left out"]
+        );
+    }
+
+    #[test]
+    fn needs_lanes_to_export() {
+        assert!(export_osu(&chart(), Some(&[])).is_err());
+        assert!(export_osu(&Chart::default(), None).is_err());
+        assert!(export_osu(&chart(), Some(&[LaneD, LaneF, LaneD])).is_err());
+    }
+
+    #[test]
+    fn rounds_timing_points_to_whole_milliseconds() {
+        let mut chart = chart();
+        chart.timing_points[0].timing = 1.0;
+        let text = export_osu(&chart, None).unwrap().text;
+        assert!(text.contains("\n17,500,4,2,0,100,1,0\n"), "{}", text);
+    }
+
+    #[test]
+    fn cleans_file_names() {
+        assert_eq!(clean("a/b: c?"), "a_b_ c_");
+        assert_eq!(file_name("songs/a\\b.ogg"), "b.ogg");
+        assert_eq!(split_title("Song"), ("Song", "machitan"));
+    }
+}
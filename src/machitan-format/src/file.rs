@@ -0,0 +1,121 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{chart::Chart, timed::TimedChart};
+
+// errors from reading and writing chart files, reported back instead of panicking
+#[derive(Debug)]
+pub enum ChartFileError {
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    Io(PathBuf, io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Serialize(toml::ser::Error),
+}
+
+impl ChartFileError {
+    fn io(path: &Path, e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => ChartFileError::NotFound(path.to_owned()),
+            io::ErrorKind::PermissionDenied => ChartFileError::PermissionDenied(path.to_owned()),
+            _ => ChartFileError::Io(path.to_owned(), e),
+        }
+    }
+
+    fn parse(path: &Path, source: &str, e: toml::de::Error) -> Self {
+        // toml only gives a byte span, turn it into something a charter can find
+        let offset = e.span().map(|span| span.start).unwrap_or(0);
+        let before = source.get(..offset).unwrap_or(source);
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+
+        ChartFileError::Parse {
+            path: path.to_owned(),
+            line,
+            column,
+            message: e.message().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ChartFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChartFileError::NotFound(path) => write!(f, "{} not found", path.display()),
+            ChartFileError::PermissionDenied(path) => {
+                write!(f, "permission denied for {}", path.display())
+            }
+            ChartFileError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ChartFileError::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "parse error in {} at line {}, column {}: {}",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+            ChartFileError::Serialize(e) => write!(f, "could not serialise beatmap: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChartFileError {}
+
+pub fn parse_chart(source: &str) -> Result<Chart, toml::de::Error> {
+    toml::from_str(source)
+}
+
+fn read_toml<T: DeserializeOwned>(path: &Path) -> Result<T, ChartFileError> {
+    let source = fs::read_to_string(path).map_err(|e| ChartFileError::io(path, e))?;
+    toml::from_str(&source).map_err(|e| ChartFileError::parse(path, &source, e))
+}
+
+pub fn read_chart(path: &Path) -> Result<Chart, ChartFileError> {
+    read_toml(path)
+}
+
+// a chart timed in seconds, see timed.rs
+pub fn read_timed_chart(path: &Path) -> Result<TimedChart, ChartFileError> {
+    read_toml(path)
+}
+
+// sorted and laid out one table per note, for charts kept under version control
+pub fn pretty_chart(chart: &Chart) -> Result<String, ChartFileError> {
+    let mut chart = chart.clone();
+    chart.notes.sort_by_key(|note| note.timing);
+    chart
+        .timing_points
+        .sort_by(|a, b| a.timing.total_cmp(&b.timing));
+    chart.keysounds.sort_by_key(|keysound| keysound.timing);
+    toml::to_string_pretty(&chart).map_err(ChartFileError::Serialize)
+}
+
+// the chart timed in seconds, laid out like pretty_chart
+pub fn pretty_timed_chart(chart: &Chart) -> Result<String, ChartFileError> {
+    let mut timed = TimedChart::from(chart);
+    timed.notes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    timed
+        .timing_points
+        .sort_by(|a, b| a.time.total_cmp(&b.time));
+    timed.keysounds.sort_by(|a, b| a.time.total_cmp(&b.time));
+    toml::to_string_pretty(&timed).map_err(ChartFileError::Serialize)
+}
+
+pub fn write_chart<T: Serialize>(path: &Path, chart: &T) -> Result<(), ChartFileError> {
+    let toml_beatmap = toml::to_string(chart).map_err(ChartFileError::Serialize)?;
+    fs::write(path, toml_beatmap).map_err(|e| ChartFileError::io(path, e))
+}
@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+
+use encoding_rs::SHIFT_JIS;
+
+use crate::{
+    chart::{Chart, ChartMetadata, Keysound, NoteId, NoteLane},
+    warn_count,
+};
+
+use super::{finish, hold_end, lane_map, later_wins, Import, ImportError, TempoMap};
+
+// BMS and BME files, measures of base 36 object ids on two digit channels
+
+// player 1 key channels from left to right, the scratch first
+const FIVE_KEYS: [char; 6] = ['6', '1', '2', '3', '4', '5'];
+const SEVEN_KEYS: [char; 8] = ['6', '1', '2', '3', '4', '5', '8', '9'];
+
+// #STOP lengths are in 1/192 of a 4/4 measure
+const STOP_STEPS_PER_BEAT: f64 = 48.0;
+
+struct Object {
+    measure: usize,
+    // how far into the measure, from 0 to 1
+    position: f64,
+    channel: [char; 2],
+    id: u16,
+}
+
+fn base36(pair: &str) -> Option<u16> {
+    u16::from_str_radix(pair, 36).ok()
+}
+
+// most BMS files are Shift JIS, newer ones UTF-8
+fn decode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(source) => source.to_owned(),
+        Err(_) => SHIFT_JIS.decode(bytes).0.into_owned(),
+    }
+}
+
+pub fn import_bms(bytes: &[u8], lanes: Option<&[NoteLane]>) -> Result<Import, ImportError> {
+    let source = decode(bytes);
+    let mut warnings = vec![];
+
+    let mut header: HashMap<String, String> = HashMap::new();
+    let mut lengths: HashMap<usize, f64> = HashMap::new();
+    let mut objects = vec![];
+    // #IF blocks nest, only their first branch gets imported
+    let mut skipping: Vec<bool> = vec![];
+    let mut random = false;
+
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        let Some(line) = line.strip_prefix('#') else {
+            continue;
+        };
+        let (command, value) = line
+            .split_once(|c: char| c.is_whitespace() || c == ':')
+            .unwrap_or((line, ""));
+        let command = command.to_uppercase();
+        let value = value.trim();
+
+        match command.as_str() {
+            "RANDOM" | "SETRANDOM" => {
+                random = true;
+                continue;
+            }
+            "IF" => {
+                skipping.push(value != "1");
+                continue;
+            }
+            "ELSE" => {
+                if let Some(skip) = skipping.last_mut() {
+                    *skip = !*skip;
+                }
+                continue;
+            }
+            "ENDIF" | "END" => {
+                skipping.pop();
+                continue;
+            }
+            _ if skipping.iter().any(|skip| *skip) => continue,
+            _ => {}
+        }
+
+        // #mmmcc:data lines, anything else is a header. Slicing the command needs it to be ASCII
+        let is_data = command.len() == 5
+            && command.is_ascii()
+            && command[..3].bytes().all(|b| b.is_ascii_digit());
+        if !is_data {
+            header.insert(command, value.to_owned());
+            continue;
+        }
+        let measure: usize = command[..3].parse().unwrap_or(0);
+        let mut channel = command[3..].chars();
+        let channel = [channel.next().unwrap_or('0'), channel.next().unwrap_or('0')];
+
+        if channel == ['0', '2'] {
+            let length = value
+                .parse()
+                .map_err(|_| ImportError::parse(index + 1, "measure length isn't a number"))?;
+            lengths.insert(measure, length);
+            continue;
+        }
+
+        let pairs: Vec<&str> = value
+            .as_bytes()
+            .chunks(2)
+            .filter_map(|pair| std::str::from_utf8(pair).ok())
+            .collect();
+        for (slot, pair) in pairs.iter().enumerate() {
+            let id = base36(pair).ok_or_else(|| {
+                ImportError::parse(index + 1, format!("{} isn't an object", pair))
+            })?;
+            if id == 0 {
+                continue;
+            }
+            objects.push(Object {
+                measure,
+                position: slot as f64 / pairs.len() as f64,
+                channel,
+                id,
+            });
+        }
+    }
+    if random {
+        warnings.push("#RANDOM blocks were imported with their first branch".into());
+    }
+
+    // beat each measure starts on, a measure is 4 beats unless its length is changed
+    let last_measure = objects
+        .iter()
+        .map(|object| object.measure)
+        .max()
+        .unwrap_or(0);
+    let mut measure_starts = vec![0.0];
+    for measure in 0..=last_measure {
+        let length = lengths.get(&measure).copied().unwrap_or(1.0);
+        measure_starts.push(measure_starts[measure] + length * 4.0);
+    }
+    let beat = |object: &Object| {
+        let start = measure_starts[object.measure];
+        start + (measure_starts[object.measure + 1] - start) * object.position
+    };
+    warn_count(
+        &mut warnings,
+        lengths.values().filter(|length| **length != 1.0).count(),
+        "measures have a changed length, measure lines after them may be off",
+    );
+
+    // the header's bpm, then changes by value on channel 03 or by #BPMxx on channel 08
+    let extended = |prefix: &str| -> HashMap<u16, f64> {
+        header
+            .iter()
+            .filter_map(|(command, value)| {
+                let id = base36(command.strip_prefix(prefix).filter(|id| id.len() == 2)?)?;
+                Some((id, value.parse().ok()?))
+            })
+            .collect()
+    };
+    let extended_bpms = extended("BPM");
+    let mut bpms: Vec<(f64, f64)> = header
+        .get("BPM")
+        .and_then(|bpm| bpm.parse().ok())
+        .map(|bpm| (0.0, bpm))
+        .into_iter()
+        .collect();
+    for object in objects.iter() {
+        let bpm = match object.channel {
+            // plain bpms are two hex digits, which read the same in base 36
+            ['0', '3'] => {
+                let (high, low) = (object.id / 36, object.id % 36);
+                (high < 16 && low < 16).then(|| f64::from(high * 16 + low))
+            }
+            ['0', '8'] => extended_bpms.get(&object.id).copied(),
+            _ => continue,
+        };
+        if let Some(bpm) = bpm {
+            bpms.push((beat(object), bpm));
+        }
+    }
+    later_wins(&mut bpms);
+    let count = bpms.len();
+    bpms.retain(|(_, bpm)| *bpm > 0.0);
+    warn_count(
+        &mut warnings,
+        count - bpms.len(),
+        "negative or zero bpm changes were left out, timing after them is off",
+    );
+    if bpms.is_empty() {
+        return Err(ImportError::Unsupported("the chart has no #BPM".into()));
+    }
+
+    let mut tempo = TempoMap {
+        offset: 0.0,
+        bpms,
+        stops: vec![],
+    };
+    let stop_lengths = extended("STOP");
+    tempo.stops = objects
+        .iter()
+        .filter(|object| object.channel == ['0', '9'])
+        .filter_map(|object| {
+            let beat = beat(object);
+            let steps = stop_lengths.get(&object.id)?;
+            Some((
+                beat,
+                steps / STOP_STEPS_PER_BEAT * 60.0 / tempo.bpm_at(beat),
+            ))
+        })
+        .collect();
+    tempo.stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let samples: HashMap<u16, String> = header
+        .iter()
+        .filter_map(|(command, sample)| {
+            let id = base36(command.strip_prefix("WAV").filter(|id| id.len() == 2)?)?;
+            Some((id, sample.clone()))
+        })
+        .collect();
+
+    // 7 key charts use channels 8 and 9 for their last two keys
+    let seven_keys = objects
+        .iter()
+        .any(|object| matches!(object.channel, ['1' | '5', '8' | '9']));
+    let channels: &[char] = if seven_keys { &SEVEN_KEYS } else { &FIVE_KEYS };
+    let lanes = lane_map(channels.len(), lanes)?;
+    let lane_of = |key: char| channels.iter().position(|c| *c == key).map(|i| lanes[i]);
+
+    let long_note_end = header.get("LNOBJ").and_then(|id| base36(id));
+    let mut imported = Chart {
+        metadata: ChartMetadata {
+            title: match header.get("SUBTITLE") {
+                Some(subtitle) => format!(
+                    "{} {}",
+                    header.get("TITLE").map_or("", String::as_str),
+                    subtitle
+                ),
+                None => header.get("TITLE").cloned().unwrap_or_default(),
+            },
+            artist: header.get("ARTIST").cloned().unwrap_or_default(),
+            charter: String::new(),
+            song: String::new(),
+        },
+        ..Default::default()
+    };
+
+    let mut objects: Vec<(f64, &Object)> = objects
+        .iter()
+        .map(|object| (beat(object), object))
+        .collect();
+    objects.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut tails = 0;
+    let mut hidden = 0;
+    let mut player_two = 0;
+    let mut mines = 0;
+    let mut animations = 0;
+    // long note channels alternate between a hold's start and end in each lane, the start's
+    // index in the notes is kept until its end comes, None when it's outside the layout
+    let mut holding: HashMap<char, Option<usize>> = HashMap::new();
+    // the latest note on each key channel, which a #LNOBJ object turns into a hold
+    let mut last: HashMap<char, usize> = HashMap::new();
+    let end_hold =
+        |note: &mut NoteId, timing: usize| note.end = hold_end(note.timing, Some(timing));
+    for (beat, object) in objects {
+        let timing = tempo.frame(beat).round().max(0.0) as usize;
+        let sample = samples.get(&object.id).cloned();
+        let [kind, key] = object.channel;
+
+        let lane = match kind {
+            // background music
+            '0' if key == '1' => None,
+            '0' if matches!(key, '4' | '6' | '7') => {
+                animations += 1;
+                continue;
+            }
+            '1' if Some(object.id) == long_note_end => {
+                match last.remove(&key) {
+                    Some(head) => end_hold(&mut imported.notes[head], timing),
+                    None => tails += 1,
+                }
+                continue;
+            }
+            '1' => {
+                let lane = lane_of(key);
+                if lane.is_some() {
+                    last.insert(key, imported.notes.len());
+                }
+                lane
+            }
+            '5' => {
+                if let Some(head) = holding.remove(&key) {
+                    if let Some(head) = head {
+                        end_hold(&mut imported.notes[head], timing);
+                    }
+                    continue;
+                }
+                let lane = lane_of(key);
+                holding.insert(key, lane.map(|_| imported.notes.len()));
+                lane
+            }
+            '2' | '6' => {
+                player_two += 1;
+                continue;
+            }
+            '3' | '4' => {
+                hidden += 1;
+                continue;
+            }
+            'D' | 'E' => {
+                mines += 1;
+                continue;
+            }
+            _ => continue,
+        };
+
+        if let Some(lane) = lane {
+            imported.notes.push(NoteId::tap(timing, lane));
+        } else if kind != '0' {
+            // a key channel outside the layout, like the foot pedal on 7
+            continue;
+        }
+        if let Some(sample) = sample {
+            imported.keysounds.push(Keysound {
+                timing,
+                lane,
+                sample,
+            });
+        }
+    }
+
+    warn_count(
+        &mut warnings,
+        holding.values().filter(|head| head.is_some()).count(),
+        "long notes never ended and were imported as taps",
+    );
+    warn_count(
+        &mut warnings,
+        tails,
+        "#LNOBJ long note ends without a note before them were left out",
+    );
+    warn_count(&mut warnings, hidden, "hidden notes were left out");
+    warn_count(&mut warnings, player_two, "player 2 notes were left out");
+    warn_count(&mut warnings, mines, "mines were left out");
+    warn_count(
+        &mut warnings,
+        animations,
+        "background animation changes were left out",
+    );
+    warn_count(
+        &mut warnings,
+        tempo.stops.len(),
+        "stops were turned into timing points, measure lines after them may be shifted",
+    );
+    imported.timing_points = tempo.timing_points();
+    finish(&mut imported, &mut warnings);
+
+    Ok(Import {
+        chart: imported,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chart::NoteLane::*, import::notes};
+
+    fn import(lines: &str) -> Result<Import, ImportError> {
+        let source = format!(
+            "#TITLE Song\n#ARTIST Someone\n#BPM 120\n#WAV01 kick.wav\n{}",
+            lines
+        );
+        import_bms(source.as_bytes(), None)
+    }
+
+    #[test]
+    fn imports_keys_and_keysounds() {
+        let import = import("#00011:0101\n#00016:0001\n#00001:01").unwrap();
+        // 5 keys plus scratch sit on the middle six lanes, scratch first
+        assert_eq!(
+            notes(&import),
+            [(0, LaneD, None), (60, LaneS, None), (60, LaneD, None)]
+        );
+        let keysounds: Vec<_> = import
+            .chart
+            .keysounds
+            .iter()
+            .map(|keysound| (keysound.timing, keysound.lane))
+            .collect();
+        assert_eq!(
+            keysounds,
+            [
+                (0, Some(LaneD)),
+                (0, None),
+                (60, Some(LaneD)),
+                (60, Some(LaneS))
+            ]
+        );
+        assert_eq!(import.chart.metadata.title, "Song");
+    }
+
+    #[test]
+    fn seven_key_charts_use_every_lane() {
+        let import = import("#00011:01\n#00019:01").unwrap();
+        assert_eq!(notes(&import), [(0, LaneS, None), (0, LaneSemicolon, None)]);
+    }
+
+    #[test]
+    fn follows_bpm_changes_and_measure_lengths() {
+        let import = import("#00002:0.5\n#00103:3C\n#00111:0101").unwrap();
+        // measure 0 is two beats long, measure 1 is at 60 bpm
+        assert_eq!(notes(&import), [(60, LaneD, None), (180, LaneD, None)]);
+        let bpms: Vec<f64> = import.chart.timing_points.iter().map(|p| p.bpm).collect();
+        assert_eq!(bpms, [120.0, 60.0]);
+    }
+
+    #[test]
+    fn long_note_channels_pair_up_into_holds() {
+        let import = import("#00051:0101\n#00052:01").unwrap();
+        assert_eq!(notes(&import), [(0, LaneD, Some(60)), (0, LaneF, None)]);
+        assert_eq!(
+            import.warnings,
+            ["1 long notes never ended and were imported as taps"]
+        );
+    }
+
+    #[test]
+    fn lnobj_ends_the_note_before_it() {
+        let import = import("#LNOBJ ZZ\n#00011:01ZZ\n#00012:00ZZ").unwrap();
+        assert_eq!(notes(&import), [(0, LaneD, Some(60))]);
+        assert_eq!(
+            import.warnings,
+            ["1 #LNOBJ long note ends without a note before them were left out"]
+        );
+    }
+
+    #[test]
+    fn random_blocks_take_their_first_branch() {
+        let import = import("#RANDOM 2\n#IF 1\n#00011:01\n#ELSE\n#00012:01\n#ENDIF").unwrap();
+        assert_eq!(notes(&import), [(0, LaneD, None)]);
+    }
+
+    #[test]
+    fn non_ascii_commands_are_headers() {
+        let import = import("#ABÄC 1\n#00Ä11:01\n#00011:01").unwrap();
+        assert_eq!(notes(&import), [(0, LaneD, None)]);
+    }
+
+    #[test]
+    fn decodes_shift_jis() {
+        let (title, _, _) = SHIFT_JIS.encode("#TITLE 曲\n#BPM 120\n#00011:01");
+        let import = import_bms(&title, None).unwrap();
+        assert_eq!(import.chart.metadata.title, "曲");
+    }
+
+    #[test]
+    fn rejects_malformed_charts() {
+        assert!(import_bms(b"#00011:01", None).is_err());
+        assert!(import("#00011:0!").is_err());
+        assert!(import("#00002:long").is_err());
+        assert!(import("#BPM -120\n#00011:01").is_err());
+    }
+}
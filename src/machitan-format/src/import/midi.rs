@@ -0,0 +1,421 @@
+use std::{collections::BTreeSet, ops::RangeInclusive};
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::{
+    chart::{Chart, ChartMetadata, NoteId, NoteLane, LANES},
+    timing::{TimingPoint, FRAMES_PER_SECOND},
+    warn_count,
+};
+
+use super::{finish, lane_map, later_wins, Import, ImportError, TempoMap};
+
+// standard MIDI files, the note ons of one track become notes with pitches mapped to lanes
+
+// a comma separated list of pitches and pitch ranges, like `36,38,42-46`
+pub fn pitch_ranges(text: &str) -> Result<Vec<RangeInclusive<u8>>, String> {
+    text.split(',')
+        .map(|range| {
+            let (low, high) = range.split_once('-').unwrap_or((range, range));
+            let pitch = |pitch: &str| {
+                pitch
+                    .trim()
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|pitch| *pitch < 128)
+                    .ok_or(format!("{} isn't a MIDI pitch from 0 to 127", pitch))
+            };
+            let (low, high) = (pitch(low)?, pitch(high)?);
+            if low > high {
+                return Err(format!(
+                    "{}-{} runs backwards, the lower pitch goes first",
+                    low, high
+                ));
+            }
+            Ok(low..=high)
+        })
+        .collect()
+}
+
+// every pitch gets its own lane when there are few enough, otherwise the range is split evenly
+fn spread_pitches(pitches: &BTreeSet<u8>, columns: usize) -> Vec<RangeInclusive<u8>> {
+    if pitches.len() <= columns {
+        return pitches.iter().map(|pitch| *pitch..=*pitch).collect();
+    }
+    let (low, high) = match (pitches.first(), pitches.last()) {
+        (Some(low), Some(high)) => (*low as usize, *high as usize),
+        _ => return vec![],
+    };
+    let width = (high - low + 1).div_ceil(columns);
+    (0..columns)
+        .map(|column| {
+            let start = low + column * width;
+            start.min(high) as u8..=(start + width - 1).min(high) as u8
+        })
+        .collect()
+}
+
+fn track_name(track: &[midly::TrackEvent]) -> Option<String> {
+    track.iter().find_map(|event| match event.kind {
+        TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
+            Some(String::from_utf8_lossy(name).trim().to_owned())
+        }
+        _ => None,
+    })
+}
+
+pub fn import_midi(
+    bytes: &[u8],
+    track: Option<usize>,
+    pitches: Option<&[RangeInclusive<u8>]>,
+    lanes: Option<&[NoteLane]>,
+) -> Result<Import, ImportError> {
+    let smf = Smf::parse(bytes).map_err(|e| ImportError::Unsupported(e.to_string()))?;
+    let mut warnings = vec![];
+
+    // note ons as (tick, pitch) for every track, tempo and time signature changes from all of them
+    let mut notes: Vec<Vec<(u64, u8)>> = vec![];
+    let mut tempos: Vec<(u64, u32)> = vec![];
+    let mut signatures: Vec<(u64, u8, u8)> = vec![];
+    for events in smf.tracks.iter() {
+        let mut tick = 0;
+        let mut track_notes = vec![];
+        for event in events {
+            tick += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, vel },
+                    ..
+                } if vel > 0 => track_notes.push((tick, key.as_int())),
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                    tempos.push((tick, tempo.as_int()))
+                }
+                TrackEventKind::Meta(MetaMessage::TimeSignature(beats, unit, _, _)) => {
+                    signatures.push((tick, beats, unit))
+                }
+                _ => {}
+            }
+        }
+        notes.push(track_notes);
+    }
+
+    // the track with the most notes when none is picked
+    let index = match track {
+        Some(index) if index < notes.len() => index,
+        Some(index) => {
+            return Err(ImportError::Unsupported(format!(
+                "there's no track {}, the file has {}",
+                index,
+                notes.len()
+            )))
+        }
+        None => (0..notes.len())
+            .max_by_key(|index| notes[*index].len())
+            .unwrap_or(0),
+    };
+    let Some(track_notes) = notes.get(index).filter(|notes| !notes.is_empty()) else {
+        return Err(ImportError::Unsupported(format!(
+            "track {} has no notes",
+            index
+        )));
+    };
+    let others: Vec<String> = notes
+        .iter()
+        .enumerate()
+        .filter(|(other, notes)| *other != index && !notes.is_empty())
+        .map(|(other, notes)| format!("track {} with {} notes", other, notes.len()))
+        .collect();
+    if !others.is_empty() {
+        warnings.push(format!(
+            "imported track {}, the file also has {}",
+            index,
+            others.join(", ")
+        ));
+    }
+
+    if lanes.is_some_and(<[NoteLane]>::is_empty) {
+        return Err(ImportError::Unsupported("no lanes given".into()));
+    }
+    let pitches = match pitches {
+        Some(pitches) => pitches.to_vec(),
+        None => {
+            let used: BTreeSet<u8> = track_notes.iter().map(|(_, pitch)| *pitch).collect();
+            spread_pitches(&used, lanes.map_or(LANES.len(), <[NoteLane]>::len))
+        }
+    };
+    let lanes = lane_map(pitches.len(), lanes)?;
+
+    let mut chart = Chart {
+        metadata: ChartMetadata {
+            title: smf
+                .tracks
+                .first()
+                .and_then(|track| track_name(track))
+                .unwrap_or_default(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // ticks to frames, through the tempo map for beat based files
+    let to_frames: Box<dyn Fn(u64) -> f64> = match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => {
+            let ticks_per_beat = ticks_per_beat.as_int() as f64;
+            let mut bpms: Vec<(f64, f64)> = tempos
+                .iter()
+                .map(|(tick, tempo)| (*tick as f64 / ticks_per_beat, 60_000_000.0 / *tempo as f64))
+                .collect();
+            later_wins(&mut bpms);
+            // MIDI's default tempo until the first change
+            if !bpms.iter().any(|(beat, _)| *beat == 0.0) {
+                bpms.insert(0, (0.0, 120.0));
+            }
+
+            let tempo = TempoMap {
+                offset: 0.0,
+                bpms,
+                stops: vec![],
+            };
+
+            // as (beat, beats per measure, beat unit), the unit is stored as a power of 2
+            signatures.sort_by_key(|(tick, _, _)| *tick);
+            let signatures: Vec<(f64, u32, u32)> = signatures
+                .iter()
+                .map(|(tick, beats, unit)| {
+                    (
+                        *tick as f64 / ticks_per_beat,
+                        *beats as u32,
+                        1u32.checked_shl(*unit as u32).unwrap_or(4),
+                    )
+                })
+                .collect();
+            // MIDI is in 4/4 until the first time signature, a later one on the same beat wins
+            let signature_at = |beat: f64| {
+                signatures
+                    .iter()
+                    .rev()
+                    .find(|(start, _, _)| *start <= beat)
+                    .map_or((4, 4), |(_, beats, unit)| (*beats, *unit))
+            };
+
+            // every tempo change and every time signature change starts a timing point
+            let mut starts: Vec<f64> = tempo
+                .bpms
+                .iter()
+                .map(|(beat, _)| *beat)
+                .chain(signatures.iter().map(|(beat, _, _)| *beat))
+                .collect();
+            starts.sort_by(f64::total_cmp);
+            starts.dedup();
+            chart.timing_points = starts
+                .into_iter()
+                .map(|beat| {
+                    let (beats_per_measure, beat_unit) = signature_at(beat);
+                    TimingPoint {
+                        timing: tempo.frame(beat),
+                        bpm: tempo.bpm_at(beat),
+                        beats_per_measure,
+                        beat_unit,
+                    }
+                })
+                .collect();
+
+            Box::new(move |tick| tempo.frame(tick as f64 / ticks_per_beat))
+        }
+        Timing::Timecode(fps, subframes) => {
+            warnings
+                .push("the file is timed in seconds, so the chart gets no timing points".into());
+            let ticks_per_second = fps.as_f32() as f64 * subframes as f64;
+            Box::new(move |tick| tick as f64 / ticks_per_second * FRAMES_PER_SECOND)
+        }
+    };
+
+    let mut unmapped = 0;
+    for (tick, pitch) in track_notes {
+        let Some(column) = pitches.iter().position(|range| range.contains(pitch)) else {
+            unmapped += 1;
+            continue;
+        };
+        chart.notes.push(NoteId::tap(
+            to_frames(*tick).round() as usize,
+            lanes[column],
+        ));
+    }
+    warn_count(
+        &mut warnings,
+        unmapped,
+        "notes had pitches outside the mapped ones and were left out",
+    );
+    finish(&mut chart, &mut warnings);
+
+    Ok(Import { chart, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chart::NoteLane::*, import::notes};
+    use midly::{Format, Header, TrackEvent};
+
+    const TICKS_PER_BEAT: u16 = 480;
+
+    // a one track file with note ons as (tick, pitch), tempo changes as (tick, microseconds
+    // per beat) and time signatures as (tick, beats, power of 2 beat unit)
+    fn midi(notes: &[(u32, u8)], tempos: &[(u32, u32)], signatures: &[(u32, u8, u8)]) -> Vec<u8> {
+        let mut events: Vec<(u32, TrackEventKind)> = tempos
+            .iter()
+            .map(|(tick, tempo)| {
+                (
+                    *tick,
+                    TrackEventKind::Meta(MetaMessage::Tempo((*tempo).into())),
+                )
+            })
+            .chain(signatures.iter().map(|(tick, beats, unit)| {
+                (
+                    *tick,
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(*beats, *unit, 24, 8)),
+                )
+            }))
+            .chain(notes.iter().map(|(tick, pitch)| {
+                let message = MidiMessage::NoteOn {
+                    key: (*pitch).into(),
+                    vel: 100.into(),
+                };
+                (
+                    *tick,
+                    TrackEventKind::Midi {
+                        channel: 0.into(),
+                        message,
+                    },
+                )
+            }))
+            .collect();
+        events.sort_by_key(|(tick, _)| *tick);
+        let mut last = 0;
+        let mut track: Vec<TrackEvent> = events
+            .into_iter()
+            .map(|(tick, kind)| {
+                let delta = tick - last;
+                last = tick;
+                TrackEvent {
+                    delta: delta.into(),
+                    kind,
+                }
+            })
+            .collect();
+        track.push(TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+        let smf = Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(TICKS_PER_BEAT.into())),
+            tracks: vec![track],
+        };
+        let mut bytes = vec![];
+        smf.write_std(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn points(import: &Import) -> Vec<(f64, f64, u32, u32)> {
+        import
+            .chart
+            .timing_points
+            .iter()
+            .map(|point| {
+                (
+                    point.timing,
+                    point.bpm,
+                    point.beats_per_measure,
+                    point.beat_unit,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_pitch_gets_a_lane_when_there_are_few() {
+        let bytes = midi(&[(0, 60), (480, 62), (960, 64)], &[], &[]);
+        let import = import_midi(&bytes, None, None, None).unwrap();
+        assert_eq!(
+            notes(&import),
+            [(0, LaneD, None), (30, LaneF, None), (60, LaneJ, None)]
+        );
+        // MIDI files play at 120 bpm in 4/4 until told otherwise
+        assert_eq!(points(&import), [(0.0, 120.0, 4, 4)]);
+    }
+
+    #[test]
+    fn follows_tempo_changes() {
+        let bytes = midi(
+            &[(0, 60), (960, 60)],
+            &[(0, 500_000), (480, 1_000_000)],
+            &[],
+        );
+        let import = import_midi(&bytes, None, None, None).unwrap();
+        assert_eq!(notes(&import), [(0, LaneF, None), (90, LaneF, None)]);
+        assert_eq!(points(&import), [(0.0, 120.0, 4, 4), (30.0, 60.0, 4, 4)]);
+    }
+
+    #[test]
+    fn time_signature_changes_start_timing_points() {
+        // 3/4 from the start, 6/8 two bars in and a tempo change in the middle of that bar
+        let bytes = midi(
+            &[(0, 60)],
+            &[(0, 500_000), (3360, 1_000_000)],
+            &[(0, 3, 2), (2880, 6, 3)],
+        );
+        let import = import_midi(&bytes, None, None, None).unwrap();
+        assert_eq!(
+            points(&import),
+            [
+                (0.0, 120.0, 3, 4),
+                (180.0, 120.0, 6, 8),
+                (210.0, 60.0, 6, 8)
+            ]
+        );
+        assert!(import.warnings.is_empty());
+    }
+
+    #[test]
+    fn maps_given_pitches_and_lanes() {
+        let bytes = midi(&[(0, 36), (0, 42), (480, 45), (0, 50)], &[], &[]);
+        let pitches = pitch_ranges("36,42-46").unwrap();
+        let import = import_midi(&bytes, None, Some(&pitches), Some(&[LaneA, LaneL])).unwrap();
+        assert_eq!(
+            notes(&import),
+            [(0, LaneA, None), (0, LaneL, None), (30, LaneL, None)]
+        );
+        assert_eq!(
+            import.warnings,
+            ["1 notes had pitches outside the mapped ones and were left out"]
+        );
+    }
+
+    #[test]
+    fn spreads_many_pitches_evenly() {
+        let pitches: BTreeSet<u8> = (60..70).collect();
+        assert_eq!(
+            spread_pitches(&pitches, 4),
+            [60..=62, 63..=65, 66..=68, 69..=69]
+        );
+        let few: BTreeSet<u8> = [40, 50].into();
+        assert_eq!(spread_pitches(&few, 4), [40..=40, 50..=50]);
+    }
+
+    #[test]
+    fn parses_pitch_ranges() {
+        assert_eq!(pitch_ranges("36, 38-40").unwrap(), [36..=36, 38..=40]);
+        assert!(pitch_ranges("40-38").is_err());
+        assert!(pitch_ranges("128").is_err());
+        assert!(pitch_ranges("snare").is_err());
+    }
+
+    #[test]
+    fn rejects_what_it_cant_import() {
+        let bytes = midi(&[(0, 60)], &[], &[]);
+        assert!(import_midi(&bytes, None, None, Some(&[])).is_err());
+        assert!(import_midi(&bytes, Some(3), None, None).is_err());
+        assert!(import_midi(&midi(&[], &[], &[]), None, None, None).is_err());
+        assert!(import_midi(b"not a midi file", None, None, None).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use std::{cmp::Reverse, fmt};
+
+use crate::{
+    chart::{Chart, NoteLane, LANES},
+    timing::{TimingPoint, FRAMES_PER_SECOND},
+};
+
+// converters from other games' chart formats, each one reports what it had to leave out
+
+pub mod bms;
+pub mod midi;
+pub mod osu;
+pub mod sm;
+
+pub struct Import {
+    pub chart: Chart,
+    // things the chart used that machitan can't play, for the charter to check
+    pub warnings: Vec<String>,
+}
+
+impl Import {
+    // audio paths in charts are relative to the assets folder, not to the chart
+    pub fn in_folder(&mut self, folder: &str) {
+        let folder = folder.trim_end_matches('/');
+        let metadata = &mut self.chart.metadata;
+        if !metadata.song.is_empty() {
+            metadata.song = format!("{}/{}", folder, metadata.song);
+        }
+        for keysound in self.chart.keysounds.iter_mut() {
+            keysound.sample = format!("{}/{}", folder, keysound.sample);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Parse { line: usize, message: String },
+    Unsupported(String),
+}
+
+impl ImportError {
+    fn parse(line: usize, message: impl Into<String>) -> Self {
+        ImportError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ImportError::Unsupported(message) => write!(f, "unsupported chart: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+fn ms_to_frames(ms: f64) -> f64 {
+    ms * FRAMES_PER_SECOND / 1000.0
+}
+
+// sorts (beat, value) changes, a later one on the same beat replaces the earlier
+fn later_wins(changes: &mut Vec<(f64, f64)>) {
+    changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    changes.reverse();
+    changes.dedup_by(|a, b| a.0 == b.0);
+    changes.reverse();
+}
+
+// tempo changes and stops of beat based formats, as (beat, bpm) and (beat, seconds)
+struct TempoMap {
+    // seconds into the song beat 0 comes before
+    offset: f64,
+    bpms: Vec<(f64, f64)>,
+    stops: Vec<(f64, f64)>,
+}
+
+impl TempoMap {
+    // stops hold the beat they're on after its notes
+    fn seconds(&self, beat: f64) -> f64 {
+        let mut seconds = 0.0 - self.offset;
+        for (index, (start, bpm)) in self.bpms.iter().enumerate() {
+            let end = self
+                .bpms
+                .get(index + 1)
+                .map_or(f64::INFINITY, |next| next.0);
+            // the first tempo also runs backwards to before its beat
+            let start = if index == 0 { start.min(beat) } else { *start };
+            if beat <= start {
+                break;
+            }
+            seconds += (beat.min(end) - start) * 60.0 / bpm;
+        }
+        let stopped: f64 = self
+            .stops
+            .iter()
+            .filter(|(stop, _)| *stop < beat)
+            .map(|(_, length)| length)
+            .sum();
+        seconds + stopped
+    }
+
+    fn frame(&self, beat: f64) -> f64 {
+        self.seconds(beat) * FRAMES_PER_SECOND
+    }
+
+    fn bpm_at(&self, beat: f64) -> f64 {
+        self.bpms
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= beat)
+            .or(self.bpms.first())
+            .map_or(120.0, |(_, bpm)| *bpm)
+    }
+
+    // a tempo change and the end of every stop restart the grid
+    fn timing_points(&self) -> Vec<TimingPoint> {
+        let mut sections: Vec<(f64, f64)> = self
+            .bpms
+            .iter()
+            .map(|(beat, bpm)| (self.frame(*beat), *bpm))
+            .chain(self.stops.iter().map(|(beat, length)| {
+                (
+                    (self.seconds(*beat) + length) * FRAMES_PER_SECOND,
+                    self.bpm_at(*beat),
+                )
+            }))
+            .collect();
+        sections.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut points: Vec<TimingPoint> = vec![];
+        for (timing, bpm) in sections {
+            // a stop ending on a tempo change leaves two points on the same frame
+            if let Some(last) = points.last_mut() {
+                if (last.timing - timing).abs() < f64::EPSILON {
+                    last.bpm = bpm;
+                    continue;
+                }
+            }
+            points.push(TimingPoint {
+                timing,
+                bpm,
+                beats_per_measure: 4,
+                beat_unit: 4,
+            });
+        }
+        points
+    }
+}
+
+// lane for every column of the chart, in order. Without one given fewer columns than
+// lanes are played on the middle ones, so 4 keys end up on dfjk
+fn lane_map(columns: usize, lanes: Option<&[NoteLane]>) -> Result<Vec<NoteLane>, ImportError> {
+    if columns == 0 {
+        return Err(ImportError::Unsupported("the chart has no columns".into()));
+    }
+    match lanes {
+        Some(lanes) if lanes.len() == columns => Ok(lanes.to_vec()),
+        Some(lanes) => Err(ImportError::Unsupported(format!(
+            "{} lanes given for a chart with {} columns",
+            lanes.len(),
+            columns
+        ))),
+        None if columns > LANES.len() => Err(ImportError::Unsupported(format!(
+            "{} columns, machitan only has {} lanes so they need mapping by hand",
+            columns,
+            LANES.len()
+        ))),
+        None => {
+            let first = (LANES.len() - columns) / 2;
+            Ok(LANES[first..first + columns].to_vec())
+        }
+    }
+}
+
+// holds shorter than a frame are taps
+pub(crate) fn hold_end(timing: usize, end: Option<usize>) -> Option<usize> {
+    end.filter(|end| *end > timing)
+}
+
+// notes are kept sorted like the game expects, ones landing on each other become one,
+// the longest hold among them
+fn finish(chart: &mut Chart, warnings: &mut Vec<String>) {
+    chart
+        .notes
+        .sort_by_key(|note| (note.timing, note.lane.index(), Reverse(note.end)));
+    let count = chart.notes.len();
+    chart
+        .notes
+        .dedup_by(|a, b| a.timing == b.timing && a.lane == b.lane);
+    if chart.notes.len() < count {
+        warnings.push(format!(
+            "{} notes fell on the same frame as another note in their lane and were merged",
+            count - chart.notes.len()
+        ));
+    }
+    chart
+        .timing_points
+        .sort_by(|a, b| a.timing.total_cmp(&b.timing));
+    chart.keysounds.sort_by_key(|keysound| keysound.timing);
+}
+
+// notes of an import as tuples, for the importers' tests
+#[cfg(test)]
+fn notes(import: &Import) -> Vec<(usize, NoteLane, Option<usize>)> {
+    import
+        .chart
+        .notes
+        .iter()
+        .map(|note| (note.timing, note.lane, note.end))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::{NoteId, NoteLane::*};
+
+    fn tempo(bpms: &[(f64, f64)], stops: &[(f64, f64)]) -> TempoMap {
+        TempoMap {
+            offset: 0.0,
+            bpms: bpms.to_vec(),
+            stops: stops.to_vec(),
+        }
+    }
+
+    #[test]
+    fn tempo_map_follows_bpm_changes() {
+        let map = tempo(&[(0.0, 120.0), (4.0, 60.0)], &[]);
+        assert_eq!(map.seconds(2.0), 1.0);
+        assert_eq!(map.seconds(4.0), 2.0);
+        assert_eq!(map.seconds(6.0), 4.0);
+        assert_eq!(map.frame(6.0), 240.0);
+        assert_eq!(map.bpm_at(3.9), 120.0);
+        assert_eq!(map.bpm_at(4.0), 60.0);
+    }
+
+    #[test]
+    fn tempo_map_offset_moves_every_beat() {
+        let map = TempoMap {
+            offset: 0.5,
+            ..tempo(&[(0.0, 120.0)], &[])
+        };
+        assert_eq!(map.seconds(0.0), -0.5);
+        assert_eq!(map.seconds(2.0), 0.5);
+    }
+
+    #[test]
+    fn tempo_map_stops_come_after_their_beat() {
+        let map = tempo(&[(0.0, 120.0)], &[(2.0, 1.0)]);
+        // notes on the stop's beat are hit before it
+        assert_eq!(map.seconds(2.0), 1.0);
+        assert_eq!(map.seconds(3.0), 2.5);
+    }
+
+    #[test]
+    fn tempo_map_timing_points_restart_after_stops() {
+        let map = tempo(&[(0.0, 120.0), (8.0, 240.0)], &[(2.0, 1.0)]);
+        let points: Vec<(f64, f64)> = map
+            .timing_points()
+            .iter()
+            .map(|point| (point.timing, point.bpm))
+            .collect();
+        // the stop ends 2 seconds in, the tempo change lands 5 seconds in
+        assert_eq!(points, [(0.0, 120.0), (120.0, 120.0), (300.0, 240.0)]);
+    }
+
+    #[test]
+    fn later_wins_keeps_the_last_change_on_a_beat() {
+        let mut changes = vec![(4.0, 60.0), (0.0, 120.0), (4.0, 90.0)];
+        later_wins(&mut changes);
+        assert_eq!(changes, [(0.0, 120.0), (4.0, 90.0)]);
+    }
+
+    #[test]
+    fn lane_map_centres_fewer_columns() {
+        assert_eq!(lane_map(4, None).unwrap(), [LaneD, LaneF, LaneJ, LaneK]);
+        assert_eq!(lane_map(8, None).unwrap(), LANES);
+        assert_eq!(lane_map(1, None).unwrap(), [LaneF]);
+    }
+
+    #[test]
+    fn lane_map_uses_given_lanes() {
+        let lanes = [LaneA, LaneS, LaneK, LaneL];
+        assert_eq!(lane_map(4, Some(&lanes)).unwrap(), lanes);
+        assert!(lane_map(3, Some(&lanes)).is_err());
+        // more columns than lanes only work with lanes given
+        assert!(lane_map(9, None).is_err());
+    }
+
+    #[test]
+    fn lane_map_needs_columns() {
+        assert!(lane_map(0, None).is_err());
+        assert!(lane_map(0, Some(&[])).is_err());
+    }
+
+    #[test]
+    fn finish_merges_notes_on_one_frame_keeping_holds() {
+        let mut import = Import {
+            chart: Chart {
+                notes: vec![
+                    NoteId::tap(30, LaneF),
+                    NoteId::tap(10, LaneD),
+                    NoteId {
+                        timing: 10,
+                        lane: LaneD,
+                        end: Some(40),
+                    },
+                    NoteId::tap(10, LaneF),
+                ],
+                ..Default::default()
+            },
+            warnings: vec![],
+        };
+        finish(&mut import.chart, &mut import.warnings);
+        assert_eq!(
+            notes(&import),
+            [(10, LaneD, Some(40)), (10, LaneF, None), (30, LaneF, None)]
+        );
+        assert_eq!(import.warnings.len(), 1);
+    }
+
+    #[test]
+    fn hold_end_turns_short_holds_into_taps() {
+        assert_eq!(hold_end(10, Some(40)), Some(40));
+        assert_eq!(hold_end(10, Some(10)), None);
+        assert_eq!(hold_end(10, None), None);
+    }
+}
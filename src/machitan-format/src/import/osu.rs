@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use crate::{
+    chart::{Chart, ChartMetadata, NoteId, NoteLane},
+    timing::TimingPoint,
+    warn_count,
+};
+
+use super::{finish, hold_end, lane_map, ms_to_frames, Import, ImportError};
+
+// osu!mania beatmaps, the .osu text format with hit object x positions as columns
+
+const MANIA_MODE: &str = "3";
+const PLAYFIELD_WIDTH: f64 = 512.0;
+// hit object type bit for a mania hold note
+const HOLD_NOTE: u32 = 128;
+
+pub fn import_osu(source: &str, lanes: Option<&[NoteLane]>) -> Result<Import, ImportError> {
+    let mut section = "";
+    let mut values: HashMap<&str, &str> = HashMap::new();
+    let mut timing_lines = vec![];
+    let mut object_lines = vec![];
+
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name;
+            continue;
+        }
+        match section {
+            "General" | "Metadata" | "Difficulty" => {
+                if let Some((key, value)) = line.split_once(':') {
+                    values.insert(key.trim(), value.trim());
+                }
+            }
+            "TimingPoints" => timing_lines.push((index + 1, line)),
+            "HitObjects" => object_lines.push((index + 1, line)),
+            _ => {}
+        }
+    }
+
+    let mode = values.get("Mode").copied().unwrap_or("0");
+    if mode != MANIA_MODE {
+        return Err(ImportError::Unsupported(format!(
+            "only osu!mania beatmaps can be imported, this one is mode {}",
+            mode
+        )));
+    }
+    let keys = values
+        .get("CircleSize")
+        .and_then(|keys| keys.parse::<f64>().ok())
+        .ok_or_else(|| ImportError::Unsupported("no key count in [Difficulty]".into()))?
+        as usize;
+    let lanes = lane_map(keys, lanes)?;
+
+    let mut warnings = vec![];
+    let title = values.get("Title").copied().unwrap_or_default();
+    let mut chart = Chart {
+        metadata: ChartMetadata {
+            title: match values.get("Version") {
+                Some(version) if !version.is_empty() => format!("{} [{}]", title, version),
+                _ => title.to_owned(),
+            },
+            artist: values.get("Artist").copied().unwrap_or_default().into(),
+            charter: values.get("Creator").copied().unwrap_or_default().into(),
+            song: values
+                .get("AudioFilename")
+                .copied()
+                .unwrap_or_default()
+                .into(),
+        },
+        ..Default::default()
+    };
+
+    let mut speed_changes = 0;
+    for (line, text) in timing_lines {
+        let fields: Vec<&str> = text.split(',').map(str::trim).collect();
+        let number = |index: usize| -> Result<f64, ImportError> {
+            let field = fields
+                .get(index)
+                .ok_or_else(|| ImportError::parse(line, "timing point is missing fields"))?;
+            field
+                .parse()
+                .map_err(|_| ImportError::parse(line, format!("{} isn't a number", field)))
+        };
+        let time = number(0)?;
+        let beat_length = number(1)?;
+        // old beatmaps leave out everything after the beat length
+        let uninherited = fields.get(6) != Some(&"0");
+        if !uninherited || beat_length <= 0.0 {
+            speed_changes += 1;
+            continue;
+        }
+        chart.timing_points.push(TimingPoint {
+            timing: ms_to_frames(time),
+            bpm: 60_000.0 / beat_length,
+            beats_per_measure: fields
+                .get(2)
+                .and_then(|meter| meter.parse().ok())
+                .unwrap_or(4),
+            beat_unit: 4,
+        });
+    }
+
+    let mut early = 0;
+    let mut keysounds = 0;
+    for (line, text) in object_lines {
+        let fields: Vec<&str> = text.split(',').map(str::trim).collect();
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .copied()
+                .ok_or_else(|| ImportError::parse(line, "hit object is missing fields"))
+        };
+        let x: f64 = field(0)?
+            .parse()
+            .map_err(|_| ImportError::parse(line, "hit object x isn't a number"))?;
+        let time: f64 = field(2)?
+            .parse()
+            .map_err(|_| ImportError::parse(line, "hit object time isn't a number"))?;
+        let kind: u32 = field(3)?
+            .parse()
+            .map_err(|_| ImportError::parse(line, "hit object type isn't a number"))?;
+
+        // the sample file is the last part of the hit sample, holds put their end time first
+        let mut sample = fields.get(5).copied().unwrap_or_default();
+        let mut end = None;
+        if kind & HOLD_NOTE != 0 {
+            let (end_time, rest) = sample.split_once(':').unwrap_or((sample, ""));
+            let end_time: f64 = end_time
+                .parse()
+                .map_err(|_| ImportError::parse(line, "hold end time isn't a number"))?;
+            end = Some(ms_to_frames(end_time).round() as usize);
+            sample = rest;
+        }
+        if sample
+            .split(':')
+            .nth(4)
+            .is_some_and(|file| !file.is_empty())
+        {
+            keysounds += 1;
+        }
+        if time < 0.0 {
+            early += 1;
+            continue;
+        }
+
+        let column = ((x * keys as f64 / PLAYFIELD_WIDTH).floor() as usize).min(keys - 1);
+        let timing = ms_to_frames(time).round() as usize;
+        chart.notes.push(NoteId {
+            timing,
+            lane: lanes[column],
+            end: hold_end(timing, end),
+        });
+    }
+
+    warn_count(
+        &mut warnings,
+        speed_changes,
+        "scroll speed changes were left out",
+    );
+    warn_count(
+        &mut warnings,
+        keysounds,
+        "keysounded notes were imported without their samples",
+    );
+    warn_count(
+        &mut warnings,
+        early,
+        "notes before the start of the song were left out",
+    );
+    if values
+        .get("AudioLeadIn")
+        .is_some_and(|lead_in| *lead_in != "0")
+    {
+        warnings.push("the audio lead-in was left out, machitan adds its own".into());
+    }
+    finish(&mut chart, &mut warnings);
+
+    Ok(Import { chart, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chart::NoteLane::*, import::notes};
+
+    fn beatmap(keys: &str, objects: &str) -> String {
+        format!(
+            "osu file format v14\n\n[General]\nAudioFilename: song.mp3\nMode: 3\n\n\
+             [Metadata]\nTitle:Song\nArtist:Someone\nCreator:Mapper\nVersion:Hard\n\n\
+             [Difficulty]\nCircleSize:{}\n\n[TimingPoints]\n0,500,4,2,0,100,1,0\n\
+             1000,-50,4,2,0,100,0,0\n\n[HitObjects]\n{}\n",
+            keys, objects
+        )
+    }
+
+    #[test]
+    fn imports_a_4k_beatmap() {
+        let import = import_osu(
+            &beatmap("4", "64,192,1000,1,0,0:0:0:0:\n448,192,500,1,0,0:0:0:0:"),
+            None,
+        )
+        .unwrap();
+        let chart = &import.chart;
+        assert_eq!(chart.metadata.title, "Song [Hard]");
+        assert_eq!(chart.metadata.song, "song.mp3");
+        assert_eq!(chart.timing_points.len(), 1);
+        assert_eq!(chart.timing_points[0].bpm, 120.0);
+        assert_eq!(notes(&import), [(30, LaneK, None), (60, LaneD, None)]);
+        assert_eq!(import.warnings, ["1 scroll speed changes were left out"]);
+    }
+
+    #[test]
+    fn imports_holds() {
+        let import = import_osu(&beatmap("4", "192,192,500,128,0,1500:0:0:0:0:"), None).unwrap();
+        assert_eq!(notes(&import), [(30, LaneF, Some(90))]);
+    }
+
+    #[test]
+    fn maps_columns_onto_given_lanes() {
+        let lanes = [LaneA, LaneS, LaneL, LaneSemicolon];
+        let chart = import_osu(&beatmap("4", "320,192,500,1,0,0:0:0:0:"), Some(&lanes))
+            .unwrap()
+            .chart;
+        assert_eq!(chart.notes[0].lane, LaneL);
+    }
+
+    #[test]
+    fn rejects_malformed_beatmaps() {
+        let source = beatmap("4", "64,192,500,1,0,0:0:0:0:");
+        assert!(import_osu(&source.replace("Mode: 3", "Mode: 0"), None).is_err());
+        assert!(import_osu(&beatmap("0", ""), None).is_err());
+        assert!(import_osu(&beatmap("12", ""), None).is_err());
+        assert!(import_osu(&beatmap("4", "64,192"), None).is_err());
+        assert!(import_osu(&beatmap("4", "64,192,soon,1,0,0:0:0:0:"), None).is_err());
+        assert!(import_osu(&beatmap("4", "64,192,500,128,0,later:0:0:0:0:"), None).is_err());
+    }
+
+    #[test]
+    fn leaves_out_notes_before_the_song() {
+        let import = import_osu(&beatmap("4", "64,192,-20,1,0,0:0:0:0:"), None).unwrap();
+        assert!(import.chart.notes.is_empty());
+        assert!(import
+            .warnings
+            .contains(&"1 notes before the start of the song were left out".to_owned()));
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+
+use crate::{
+    chart::{Chart, ChartMetadata, NoteId, NoteLane},
+    warn_count,
+};
+
+use super::{finish, hold_end, lane_map, Import, ImportError, TempoMap};
+
+// StepMania .sm and .ssc files, a song with any number of charts in measure based note rows
+
+#[derive(Default)]
+struct StepChart {
+    tags: HashMap<String, String>,
+    notes: String,
+}
+
+impl StepChart {
+    fn tag(&self, name: &str) -> &str {
+        self.tags.get(name).map_or("", String::as_str)
+    }
+
+    fn meter(&self) -> u32 {
+        self.tag("METER").trim().parse().unwrap_or(0)
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.tag("STEPSTYPE"),
+            self.tag("DIFFICULTY"),
+            self.meter()
+        )
+    }
+}
+
+// #TAG:value; pairs with // comments removed, values can span lines
+fn tags(source: &str) -> Vec<(String, String)> {
+    let source: String = source
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut tags = vec![];
+    let mut rest = source.as_str();
+    while let Some(start) = rest.find('#') {
+        rest = &rest[start + 1..];
+        let Some((tag, value)) = rest.split_once(':') else {
+            break;
+        };
+        // a missing ; ends the value at the next tag instead
+        let end = value
+            .find(';')
+            .or_else(|| value.find("\n#"))
+            .unwrap_or(value.len());
+        tags.push((tag.trim().to_uppercase(), value[..end].trim().to_owned()));
+        rest = &value[end..];
+    }
+    tags
+}
+
+// beat=value lists like #BPMS and #STOPS
+fn beat_values(tag: &str, value: &str) -> Result<Vec<(f64, f64)>, ImportError> {
+    let mut values = vec![];
+    for pair in value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let parsed = pair.split_once('=').and_then(|(beat, value)| {
+            Some((beat.trim().parse().ok()?, value.trim().parse().ok()?))
+        });
+        let Some(parsed) = parsed else {
+            return Err(ImportError::Unsupported(format!(
+                "#{} has an unreadable entry {}",
+                tag, pair
+            )));
+        };
+        values.push(parsed);
+    }
+    values.sort_by(|a: &(f64, f64), b| a.0.total_cmp(&b.0));
+    Ok(values)
+}
+
+// picks the chart by difficulty name or description, the hardest one when not given
+fn pick_chart<'a>(
+    charts: &'a [StepChart],
+    difficulty: Option<&str>,
+) -> Result<&'a StepChart, ImportError> {
+    let chart = match difficulty {
+        Some(difficulty) => charts.iter().find(|chart| {
+            chart.tag("DIFFICULTY").eq_ignore_ascii_case(difficulty)
+                || chart.tag("DESCRIPTION").eq_ignore_ascii_case(difficulty)
+        }),
+        None => charts.iter().max_by_key(|chart| chart.meter()),
+    };
+    chart.ok_or_else(|| {
+        let names: Vec<String> = charts.iter().map(StepChart::name).collect();
+        match difficulty {
+            Some(difficulty) => ImportError::Unsupported(format!(
+                "no {} chart, the file has {}",
+                difficulty,
+                names.join(", ")
+            )),
+            None => ImportError::Unsupported("the file has no charts".into()),
+        }
+    })
+}
+
+pub fn import_sm(
+    source: &str,
+    difficulty: Option<&str>,
+    lanes: Option<&[NoteLane]>,
+) -> Result<Import, ImportError> {
+    let mut song: HashMap<String, String> = HashMap::new();
+    let mut charts: Vec<StepChart> = vec![];
+    // .ssc charts start with #NOTEDATA and take the tags up to their #NOTES
+    let mut in_ssc_chart = false;
+
+    for (tag, value) in tags(source) {
+        match tag.as_str() {
+            "NOTEDATA" => {
+                charts.push(StepChart::default());
+                in_ssc_chart = true;
+            }
+            "NOTES" | "NOTES2" if in_ssc_chart => {
+                if let Some(chart) = charts.last_mut() {
+                    chart.notes = value;
+                }
+                in_ssc_chart = false;
+            }
+            // .sm packs the chart's description into the #NOTES value
+            "NOTES" => {
+                let fields: Vec<&str> = value.splitn(6, ':').map(str::trim).collect();
+                let [steps_type, description, difficulty, meter, _radar, notes] = fields[..] else {
+                    return Err(ImportError::Unsupported(
+                        "a #NOTES tag doesn't have its 6 fields".into(),
+                    ));
+                };
+                let tags = [
+                    ("STEPSTYPE", steps_type),
+                    ("DESCRIPTION", description),
+                    ("DIFFICULTY", difficulty),
+                    ("METER", meter),
+                ];
+                charts.push(StepChart {
+                    tags: tags
+                        .iter()
+                        .map(|(tag, value)| (tag.to_string(), value.to_string()))
+                        .collect(),
+                    notes: notes.to_owned(),
+                });
+            }
+            _ if in_ssc_chart => {
+                if let Some(chart) = charts.last_mut() {
+                    chart.tags.insert(tag, value);
+                }
+            }
+            _ => {
+                song.insert(tag, value);
+            }
+        }
+    }
+
+    let chart = pick_chart(&charts, difficulty)?;
+    let mut warnings = vec![];
+    let others: Vec<String> = charts
+        .iter()
+        .filter(|other| !std::ptr::eq(*other, chart))
+        .map(StepChart::name)
+        .collect();
+    if !others.is_empty() {
+        warnings.push(format!(
+            "imported {}, the file also has {}",
+            chart.name(),
+            others.join(", ")
+        ));
+    }
+
+    // timing tags of a .ssc chart replace the song's
+    let timing_tag = |tag: &str| {
+        chart
+            .tags
+            .get(tag)
+            .or_else(|| song.get(tag))
+            .or_else(|| (tag == "STOPS").then(|| song.get("FREEZES")).flatten())
+            .map_or("", String::as_str)
+    };
+    for tag in ["DELAYS", "WARPS"] {
+        if !timing_tag(tag).is_empty() {
+            warnings.push(format!(
+                "#{} aren't supported, timing after them is off",
+                tag
+            ));
+        }
+    }
+
+    let mut tempo = TempoMap {
+        offset: timing_tag("OFFSET").parse().unwrap_or(0.0),
+        bpms: beat_values("BPMS", timing_tag("BPMS"))?,
+        stops: beat_values("STOPS", timing_tag("STOPS"))?,
+    };
+    let count = tempo.bpms.len();
+    tempo.bpms.retain(|(_, bpm)| *bpm > 0.0);
+    warn_count(
+        &mut warnings,
+        count - tempo.bpms.len(),
+        "negative bpm warps were left out, timing after them is off",
+    );
+    if tempo.bpms.is_empty() {
+        return Err(ImportError::Unsupported("the song has no #BPMS".into()));
+    }
+
+    let title = song.get("TITLE").map_or("", String::as_str);
+    let mut imported = Chart {
+        metadata: ChartMetadata {
+            title: format!("{} [{}]", title, chart.tag("DIFFICULTY")),
+            artist: song.get("ARTIST").cloned().unwrap_or_default(),
+            charter: [
+                chart.tag("CREDIT"),
+                song.get("CREDIT").map_or("", String::as_str),
+            ]
+            .into_iter()
+            .find(|credit| !credit.is_empty())
+            .unwrap_or_default()
+            .into(),
+            song: song.get("MUSIC").cloned().unwrap_or_default(),
+        },
+        ..Default::default()
+    };
+
+    imported.timing_points = tempo.timing_points();
+
+    let measures: Vec<Vec<&str>> = chart
+        .notes
+        .split(',')
+        .map(|measure| {
+            measure
+                .lines()
+                .map(str::trim)
+                .filter(|row| !row.is_empty())
+                .collect()
+        })
+        .collect();
+    let columns = measures
+        .iter()
+        .flatten()
+        .map(|row| row.len())
+        .max()
+        .unwrap_or(0);
+    let lanes = lane_map(columns, lanes)?;
+
+    // holds waiting for their tail, by column, with the index of their head in the notes
+    // or None for heads left out for coming before the song
+    let mut open: HashMap<usize, Option<usize>> = HashMap::new();
+    let mut rolls = 0;
+    let mut tails = 0;
+    let mut lifts = 0;
+    let mut skipped = 0;
+    let mut early = 0;
+    for (measure, rows) in measures.iter().enumerate() {
+        for (row, notes) in rows.iter().enumerate() {
+            let beat = (measure as f64 + row as f64 / rows.len() as f64) * 4.0;
+            let timing = tempo.frame(beat).round();
+            for (column, note) in notes.chars().enumerate() {
+                match note {
+                    '1' | '2' => {}
+                    '4' => rolls += 1,
+                    // hold and roll tails
+                    '3' => {
+                        match open.remove(&column) {
+                            Some(Some(head)) => {
+                                let note = &mut imported.notes[head];
+                                note.end = hold_end(note.timing, Some(timing as usize));
+                            }
+                            Some(None) => {}
+                            None => tails += 1,
+                        }
+                        continue;
+                    }
+                    'L' => lifts += 1,
+                    // mines, fakes and keysound only notes aren't played
+                    'M' | 'F' | 'K' => {
+                        skipped += 1;
+                        continue;
+                    }
+                    _ => continue,
+                }
+                let head = matches!(note, '2' | '4');
+                if timing < 0.0 {
+                    early += 1;
+                    if head {
+                        open.insert(column, None);
+                    }
+                    continue;
+                }
+                if head {
+                    open.insert(column, Some(imported.notes.len()));
+                }
+                imported
+                    .notes
+                    .push(NoteId::tap(timing as usize, lanes[column]));
+            }
+        }
+    }
+
+    warn_count(&mut warnings, rolls, "rolls were imported as holds");
+    warn_count(
+        &mut warnings,
+        open.values().filter(|head| head.is_some()).count(),
+        "holds never ended and were imported as taps",
+    );
+    warn_count(
+        &mut warnings,
+        tails,
+        "hold ends without a hold were left out",
+    );
+    warn_count(&mut warnings, lifts, "lifts were imported as taps");
+    warn_count(
+        &mut warnings,
+        skipped,
+        "mines, fakes and keysound notes were left out",
+    );
+    warn_count(
+        &mut warnings,
+        early,
+        "notes before the start of the song were left out",
+    );
+    warn_count(
+        &mut warnings,
+        tempo.stops.len(),
+        "stops were turned into timing points, measure lines after them may be shifted",
+    );
+    finish(&mut imported, &mut warnings);
+
+    Ok(Import {
+        chart: imported,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chart::NoteLane::*, import::notes};
+
+    fn song(tags: &str, notes: &str) -> String {
+        format!(
+            "#TITLE:Song;\n#ARTIST:Someone;\n#MUSIC:song.ogg;\n#OFFSET:0;\n{}\n\
+             #NOTES:\n dance-single:\n:\n Hard:\n 9:\n 0,0,0,0,0:\n{}\n;\n",
+            tags, notes
+        )
+    }
+
+    #[test]
+    fn imports_rows_and_measures() {
+        let import = import_sm(
+            &song(
+                "#BPMS:0=120;\n#CREDIT:Mapper;",
+                "1000\n0100\n0010\n0001\n,\n1001",
+            ),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(import.chart.metadata.title, "Song [Hard]");
+        assert_eq!(import.chart.metadata.charter, "Mapper");
+        assert_eq!(
+            notes(&import),
+            [
+                (0, LaneD, None),
+                (30, LaneF, None),
+                (60, LaneJ, None),
+                (90, LaneK, None),
+                (120, LaneD, None),
+                (120, LaneK, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_holds_and_rolls() {
+        let import =
+            import_sm(&song("#BPMS:0=120;", "2004\n0000\n3000\n0003"), None, None).unwrap();
+        assert_eq!(notes(&import), [(0, LaneD, Some(60)), (0, LaneK, Some(90))]);
+        assert_eq!(import.warnings, ["1 rolls were imported as holds"]);
+    }
+
+    #[test]
+    fn unmatched_hold_parts_are_reported() {
+        let import = import_sm(&song("#BPMS:0=120;", "2000\n0300"), None, None).unwrap();
+        assert_eq!(notes(&import), [(0, LaneD, None)]);
+        assert_eq!(
+            import.warnings,
+            [
+                "1 holds never ended and were imported as taps",
+                "1 hold ends without a hold were left out",
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_bpm_changes_stops_and_the_offset() {
+        let import = import_sm(
+            &song(
+                "#BPMS:0=120,4=60;\n#STOPS:2=0.5;",
+                "1000\n0000\n1000\n0000\n,\n1000\n0000\n1000\n0000",
+            ),
+            None,
+            None,
+        )
+        .unwrap();
+        let timings: Vec<usize> = notes(&import).iter().map(|note| note.0).collect();
+        // the stop on beat 2 holds for 30 frames after its note, beat 4 slows down to 60 bpm
+        assert_eq!(timings, [0, 60, 150, 270]);
+        let points: Vec<(f64, f64)> = import
+            .chart
+            .timing_points
+            .iter()
+            .map(|point| (point.timing, point.bpm))
+            .collect();
+        assert_eq!(points, [(0.0, 120.0), (90.0, 120.0), (150.0, 60.0)]);
+
+        let late = import_sm(
+            &song("#BPMS:0=120;", "1000").replace("#OFFSET:0", "#OFFSET:-0.5"),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(notes(&late), [(30, LaneD, None)]);
+    }
+
+    #[test]
+    fn picks_the_chart_asked_for() {
+        let source = song("#BPMS:0=120;", "1000")
+            + "#NOTES:\n dance-single:\n:\n Easy:\n 2:\n 0,0,0,0,0:\n0001\n;\n";
+        let easy = import_sm(&source, Some("easy"), None).unwrap();
+        assert_eq!(easy.chart.metadata.title, "Song [Easy]");
+        assert_eq!(notes(&easy), [(0, LaneK, None)]);
+        // the hardest chart is the default
+        let hard = import_sm(&source, None, None).unwrap();
+        assert_eq!(hard.chart.metadata.title, "Song [Hard]");
+        assert!(import_sm(&source, Some("challenge"), None).is_err());
+    }
+
+    #[test]
+    fn rejects_songs_without_a_tempo() {
+        assert!(import_sm(&song("", "1000"), None, None).is_err());
+        assert!(import_sm(&song("#BPMS:0=-120;", "1000"), None, None).is_err());
+        assert!(import_sm(&song("#BPMS:zero;", "1000"), None, None).is_err());
+        assert!(import_sm("#TITLE:Song;", None, None).is_err());
+    }
+}
@@ -0,0 +1,19 @@
+// the chart format shared by the game and the chart tools, kept free of Bevy
+// so charts can be checked and converted without building the game
+
+pub mod chart;
+pub mod export;
+pub mod file;
+pub mod import;
+pub mod timed;
+pub mod timing;
+pub mod validate;
+
+pub use chart::{Chart, ChartMetadata, Keysound, NoteId, NoteLane, FRAMES_TO_TIMING, LANES};
+
+// importers and exporters sum up what they couldn't carry over as "<count> <message>"
+pub(crate) fn warn_count(warnings: &mut Vec<String>, count: usize, message: &str) {
+    if count > 0 {
+        warnings.push(format!("{} {}", count, message));
+    }
+}
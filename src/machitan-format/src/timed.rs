@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chart::{Chart, ChartMetadata, Keysound, NoteId, NoteLane},
+    import::hold_end,
+    timing::{default_beats, TimingPoint, FRAMES_PER_SECOND},
+};
+
+// charts timed in seconds on the song instead of 60 fps frames, for tools and editors
+// that don't count frames. Converting back rounds notes to the nearest frame
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct TimedChart {
+    #[serde(default)]
+    pub metadata: ChartMetadata,
+    #[serde(default)]
+    pub timing_points: Vec<TimedTimingPoint>,
+    pub notes: Vec<TimedNote>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keysounds: Vec<TimedKeysound>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct TimedTimingPoint {
+    // seconds the section's first beat lands on
+    pub time: f64,
+    pub bpm: f64,
+    #[serde(default = "default_beats")]
+    pub beats_per_measure: u32,
+    #[serde(default = "default_beats")]
+    pub beat_unit: u32,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct TimedNote {
+    pub time: f64,
+    pub lane: NoteLane,
+    // seconds a hold is let go on, taps don't have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TimedKeysound {
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lane: Option<NoteLane>,
+    pub sample: String,
+}
+
+fn seconds(frames: f64) -> f64 {
+    frames / FRAMES_PER_SECOND
+}
+
+// notes can't come before the song starts
+fn frame(seconds: f64) -> usize {
+    (seconds * FRAMES_PER_SECOND).round().max(0.0) as usize
+}
+
+impl From<&Chart> for TimedChart {
+    fn from(chart: &Chart) -> Self {
+        TimedChart {
+            metadata: chart.metadata.clone(),
+            timing_points: chart
+                .timing_points
+                .iter()
+                .map(|point| TimedTimingPoint {
+                    time: seconds(point.timing),
+                    bpm: point.bpm,
+                    beats_per_measure: point.beats_per_measure,
+                    beat_unit: point.beat_unit,
+                })
+                .collect(),
+            notes: chart
+                .notes
+                .iter()
+                .map(|note| TimedNote {
+                    time: seconds(note.timing as f64),
+                    lane: note.lane,
+                    end: note.end.map(|end| seconds(end as f64)),
+                })
+                .collect(),
+            keysounds: chart
+                .keysounds
+                .iter()
+                .map(|keysound| TimedKeysound {
+                    time: seconds(keysound.timing as f64),
+                    lane: keysound.lane,
+                    sample: keysound.sample.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&TimedChart> for Chart {
+    fn from(chart: &TimedChart) -> Self {
+        Chart {
+            metadata: chart.metadata.clone(),
+            timing_points: chart
+                .timing_points
+                .iter()
+                .map(|point| TimingPoint {
+                    timing: point.time * FRAMES_PER_SECOND,
+                    bpm: point.bpm,
+                    beats_per_measure: point.beats_per_measure,
+                    beat_unit: point.beat_unit,
+                })
+                .collect(),
+            notes: chart
+                .notes
+                .iter()
+                .map(|note| {
+                    let timing = frame(note.time);
+                    NoteId {
+                        timing,
+                        lane: note.lane,
+                        end: hold_end(timing, note.end.map(frame)),
+                    }
+                })
+                .collect(),
+            keysounds: chart
+                .keysounds
+                .iter()
+                .map(|keysound| Keysound {
+                    timing: frame(keysound.time),
+                    lane: keysound.lane,
+                    sample: keysound.sample.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chart::NoteLane::*, timing::DEFAULT_TIMING_POINT};
+
+    #[test]
+    fn converts_to_seconds_and_back() {
+        let chart = Chart {
+            timing_points: vec![TimingPoint {
+                timing: 12.0,
+                ..DEFAULT_TIMING_POINT
+            }],
+            notes: vec![
+                NoteId::tap(30, LaneD),
+                NoteId {
+                    timing: 60,
+                    lane: LaneF,
+                    end: Some(150),
+                },
+            ],
+            keysounds: vec![Keysound {
+                timing: 30,
+                lane: None,
+                sample: "kick.wav".into(),
+            }],
+            ..Default::default()
+        };
+        let timed = TimedChart::from(&chart);
+        assert_eq!(timed.timing_points[0].time, 0.2);
+        assert_eq!(
+            timed.notes[1],
+            TimedNote {
+                time: 1.0,
+                lane: LaneF,
+                end: Some(2.5),
+            }
+        );
+        assert_eq!(timed.keysounds[0].time, 0.5);
+
+        let back = Chart::from(&timed);
+        assert_eq!(back.notes, chart.notes);
+        assert_eq!(back.timing_points, chart.timing_points);
+        assert_eq!(back.keysounds[0].timing, 30);
+    }
+
+    #[test]
+    fn rounds_to_frames() {
+        let timed = TimedChart {
+            notes: vec![
+                TimedNote {
+                    time: 0.509,
+                    lane: LaneD,
+                    end: Some(0.51),
+                },
+                TimedNote {
+                    time: -0.1,
+                    lane: LaneF,
+                    end: None,
+                },
+            ],
+            ..Default::default()
+        };
+        // a hold shorter than a frame becomes a tap, notes before the song start on it
+        assert_eq!(
+            Chart::from(&timed).notes,
+            [NoteId::tap(31, LaneD), NoteId::tap(0, LaneF)]
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+
+use crate::chart::NoteId;
+
+// timing math on the 60 frames per second song clock
+
+pub const FRAMES_PER_SECOND: f64 = 60.0;
+
+// tempo section of a chart, lasting until the next timing point
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct TimingPoint {
+    // frame the section's first beat lands on
+    pub timing: f64,
+    pub bpm: f64,
+    #[serde(default = "default_beats")]
+    pub beats_per_measure: u32,
+    #[serde(default = "default_beats")]
+    pub beat_unit: u32,
+}
+
+pub(crate) fn default_beats() -> u32 {
+    4
+}
+
+// used for charts that don't define any timing points yet
+pub const DEFAULT_TIMING_POINT: TimingPoint = TimingPoint {
+    timing: 0.0,
+    bpm: 120.0,
+    beats_per_measure: 4,
+    beat_unit: 4,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BeatLine {
+    Measure,
+    Beat,
+    Snap,
+}
+
+// length of one beat in frames
+pub fn beat_frames(bpm: f64) -> f64 {
+    FRAMES_PER_SECOND * 60.0 / bpm
+}
+
+// snaps are note values over a 4/4 measure, so 1/4 is a beat and 1/16 a quarter beat
+pub fn snap_frames(bpm: f64, divisor: u32) -> f64 {
+    beat_frames(bpm) * 4.0 / divisor as f64
+}
+
+// moves a timing to the closest snap line, offset is where the first beat lands
+pub fn quantise(timing: usize, bpm: f64, offset: f64, divisor: u32) -> usize {
+    let step = snap_frames(bpm, divisor);
+    let lines = ((timing as f64 - offset) / step).round();
+    (offset + lines * step).round().max(0.0) as usize
+}
+
+// timing point in effect at a frame, notes before the first one use the first
+pub fn timing_point_at(points: &[TimingPoint], timing: f64) -> &TimingPoint {
+    points
+        .iter()
+        .rev()
+        .find(|point| point.timing <= timing)
+        .or(points.first())
+        .unwrap_or(&DEFAULT_TIMING_POINT)
+}
+
+// quantise against whichever tempo section the timing falls in
+pub fn quantise_to(points: &[TimingPoint], timing: usize, divisor: u32) -> usize {
+    let point = timing_point_at(points, timing as f64);
+    quantise(timing, point.bpm, point.timing, divisor)
+}
+
+// snaps a note's start and a hold's end, a hold squeezed down to nothing becomes a tap
+pub fn quantise_note(points: &[TimingPoint], note: &NoteId, divisor: u32) -> NoteId {
+    let timing = quantise_to(points, note.timing, divisor);
+    NoteId {
+        timing,
+        lane: note.lane,
+        end: note
+            .end
+            .map(|end| quantise_to(points, end, divisor))
+            .filter(|end| *end > timing),
+    }
+}
+
+// grid lines between two frames, taking tempo and time signature changes into account
+pub fn beat_lines(
+    points: &[TimingPoint],
+    from: f64,
+    to: f64,
+    divisor: u32,
+) -> Vec<(f64, BeatLine)> {
+    let default_points = [DEFAULT_TIMING_POINT];
+    let points = if points.is_empty() {
+        &default_points[..]
+    } else {
+        points
+    };
+
+    let mut lines = vec![];
+    for (index, point) in points.iter().enumerate() {
+        // the first section also extends backwards to the start of the song
+        let start = if index == 0 {
+            from.min(point.timing)
+        } else {
+            point.timing
+        };
+        let end = points.get(index + 1).map_or(to, |next| next.timing);
+        if end < from || start > to {
+            continue;
+        }
+
+        // a negative, zero or infinite tempo would never get past its first line
+        let step = snap_frames(point.bpm, divisor);
+        if !step.is_finite() || step <= 0.0 {
+            continue;
+        }
+        let snaps_per_beat = (beat_frames(point.bpm) / step).round().max(1.0) as i64;
+        let snaps_per_measure = snaps_per_beat * point.beats_per_measure.max(1) as i64;
+
+        let last = index == points.len() - 1;
+        let mut line = ((start.max(from) - point.timing) / step).ceil() as i64;
+        loop {
+            let timing = point.timing + line as f64 * step;
+            if timing > to || (!last && timing >= end) {
+                break;
+            }
+
+            let kind = if line.rem_euclid(snaps_per_measure) == 0 {
+                BeatLine::Measure
+            } else if line.rem_euclid(snaps_per_beat) == 0 {
+                BeatLine::Beat
+            } else {
+                BeatLine::Snap
+            };
+            lines.push((timing, kind));
+            line += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::NoteLane;
+
+    fn point(timing: f64, bpm: f64) -> TimingPoint {
+        TimingPoint {
+            timing,
+            bpm,
+            ..DEFAULT_TIMING_POINT
+        }
+    }
+
+    #[test]
+    fn quantise_snaps_to_the_closest_line() {
+        // 120 bpm is 30 frames a beat, so 1/16 snaps every 7.5 frames
+        assert_eq!(quantise(31, 120.0, 0.0, 4), 30);
+        assert_eq!(quantise(44, 120.0, 0.0, 4), 30);
+        assert_eq!(quantise(46, 120.0, 0.0, 4), 60);
+        assert_eq!(quantise(9, 120.0, 0.0, 16), 8);
+        // lines are counted from the offset
+        assert_eq!(quantise(14, 120.0, 12.0, 4), 12);
+        // and don't go before the song
+        assert_eq!(quantise(2, 120.0, 20.0, 4), 0);
+    }
+
+    #[test]
+    fn quantise_to_uses_the_section_the_timing_is_in() {
+        let points = [point(0.0, 120.0), point(60.0, 180.0)];
+        assert_eq!(quantise_to(&points, 41, 4), 30);
+        // 180 bpm beats are 20 frames long from frame 60
+        assert_eq!(quantise_to(&points, 91, 4), 100);
+        // before the first point the first one's grid is used
+        assert_eq!(quantise_to(&[point(10.0, 120.0)], 1, 4), 10);
+        // no points at all quantise against the default 120 bpm
+        assert_eq!(quantise_to(&[], 29, 4), 30);
+    }
+
+    #[test]
+    fn quantise_note_snaps_both_ends_of_a_hold() {
+        let points = [point(0.0, 120.0)];
+        let hold = NoteId {
+            timing: 28,
+            lane: NoteLane::LaneD,
+            end: Some(62),
+        };
+        let snapped = quantise_note(&points, &hold, 4);
+        assert_eq!((snapped.timing, snapped.end), (30, Some(60)));
+
+        let tap = NoteId::tap(32, NoteLane::LaneF);
+        assert_eq!(
+            quantise_note(&points, &tap, 4),
+            NoteId::tap(30, NoteLane::LaneF)
+        );
+
+        // a hold that snaps down to nothing becomes a tap
+        let short = NoteId {
+            timing: 29,
+            lane: NoteLane::LaneJ,
+            end: Some(33),
+        };
+        assert_eq!(quantise_note(&points, &short, 4).end, None);
+    }
+
+    #[test]
+    fn beat_lines_mark_measures_and_beats() {
+        let lines = beat_lines(&[point(0.0, 120.0)], 0.0, 120.0, 8);
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], (0.0, BeatLine::Measure));
+        assert_eq!(lines[1], (15.0, BeatLine::Snap));
+        assert_eq!(lines[2], (30.0, BeatLine::Beat));
+        assert_eq!(lines[8], (120.0, BeatLine::Measure));
+    }
+
+    #[test]
+    fn beat_lines_follow_bpm_changes() {
+        let points = [point(0.0, 120.0), point(60.0, 60.0)];
+        let lines: Vec<f64> = beat_lines(&points, 0.0, 180.0, 4)
+            .into_iter()
+            .map(|(timing, _)| timing)
+            .collect();
+        assert_eq!(lines, [0.0, 30.0, 60.0, 120.0, 180.0]);
+    }
+
+    #[test]
+    fn beat_lines_skip_sections_without_a_usable_bpm() {
+        for bpm in [0.0, -120.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let points = [point(0.0, bpm), point(60.0, 120.0)];
+            let lines = beat_lines(&points, 0.0, 120.0, 4);
+            assert_eq!(lines.first(), Some(&(60.0, BeatLine::Measure)), "{}", bpm);
+            assert!(lines.iter().all(|(timing, _)| timing.is_finite()));
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use std::{collections::HashMap, fmt};
+
+use crate::chart::{Chart, NoteId, FRAMES_TO_TIMING};
+
+// chart problems found before they turn into crashes or unplayable patterns
+
+// two notes in one lane closer than this can't be hit separately
+const MIN_LANE_GAP: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    // frame the problem is at, if it's about a spot in the chart
+    pub timing: Option<usize>,
+    pub message: String,
+}
+
+impl Issue {
+    fn new(severity: Severity, timing: Option<usize>, message: String) -> Self {
+        Issue {
+            severity,
+            timing,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.timing {
+            Some(timing) => write!(f, "{} at frame {}: {}", self.severity, timing, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+// issues sorted by where they are in the chart, chart wide ones first
+pub fn validate(chart: &Chart) -> Vec<Issue> {
+    let mut issues = vec![];
+
+    if chart.notes.is_empty() {
+        issues.push(Issue::new(
+            Severity::Warning,
+            None,
+            "the chart has no notes".into(),
+        ));
+    }
+    // keysounded charts can play without a song
+    if chart.metadata.song.is_empty() && chart.keysounds.is_empty() {
+        issues.push(Issue::new(
+            Severity::Info,
+            None,
+            "no song in the metadata, it has to be given when loading".into(),
+        ));
+    }
+
+    for (index, point) in chart.timing_points.iter().enumerate() {
+        if !point.bpm.is_finite()
+            || point.bpm <= 0.0
+            || point.beats_per_measure == 0
+            || point.beat_unit == 0
+        {
+            issues.push(Issue::new(
+                Severity::Error,
+                Some(point.timing.max(0.0) as usize),
+                format!(
+                    "timing point {} needs a positive, finite bpm and time signature",
+                    index
+                ),
+            ));
+        }
+        if point.timing < 0.0 {
+            issues.push(Issue::new(
+                Severity::Error,
+                None,
+                format!("timing point {} starts before the song", index),
+            ));
+        }
+    }
+    if chart
+        .timing_points
+        .windows(2)
+        .any(|pair| pair[1].timing <= pair[0].timing)
+    {
+        issues.push(Issue::new(
+            Severity::Error,
+            None,
+            "timing points aren't in order".into(),
+        ));
+    }
+
+    for pair in chart.notes.windows(2) {
+        if pair[1].timing < pair[0].timing {
+            issues.push(Issue::new(
+                Severity::Error,
+                Some(pair[1].timing),
+                format!(
+                    "notes aren't in order, this one comes after frame {}",
+                    pair[0].timing
+                ),
+            ));
+        }
+    }
+
+    if chart
+        .keysounds
+        .windows(2)
+        .any(|pair| pair[1].timing < pair[0].timing)
+    {
+        issues.push(Issue::new(
+            Severity::Error,
+            None,
+            "keysounds aren't in order".into(),
+        ));
+    }
+
+    // compare each note against the last one seen in its lane, in time order
+    let mut notes: Vec<_> = chart.notes.iter().collect();
+    notes.sort_by_key(|note| note.timing);
+    if let Some(first) = notes.first().filter(|note| note.timing < FRAMES_TO_TIMING) {
+        issues.push(Issue::new(
+            Severity::Info,
+            Some(first.timing),
+            format!(
+                "the song gets {} frames of silence first so the opening notes can scroll in",
+                FRAMES_TO_TIMING - first.timing
+            ),
+        ));
+    }
+
+    for note in notes.iter() {
+        if note.end.is_some_and(|end| end <= note.timing) {
+            issues.push(Issue::new(
+                Severity::Error,
+                Some(note.timing),
+                format!("hold in {:?} doesn't end after it starts", note.lane),
+            ));
+        }
+    }
+
+    let mut previous: HashMap<_, &NoteId> = HashMap::new();
+    for note in notes {
+        let Some(last) = previous.insert(note.lane, note) else {
+            continue;
+        };
+        // the gap is counted from the end of a hold
+        let gap = note.timing as isize - last.last_frame() as isize;
+        if note.timing == last.timing {
+            issues.push(Issue::new(
+                Severity::Error,
+                Some(note.timing),
+                format!("duplicate note in {:?}", note.lane),
+            ));
+        } else if gap <= 0 {
+            issues.push(Issue::new(
+                Severity::Error,
+                Some(note.timing),
+                format!("{:?} lands inside the hold before it", note.lane),
+            ));
+        } else if gap < MIN_LANE_GAP as isize {
+            issues.push(Issue::new(
+                Severity::Warning,
+                Some(note.timing),
+                format!(
+                    "{:?} is only {} frames after the previous note in its lane",
+                    note.lane, gap
+                ),
+            ));
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.timing);
+    issues
+}
+
+// console lines for a validation, capped so a broken chart doesn't flood the console
+const REPORT_LIMIT: usize = 20;
+
+pub fn report(issues: &[Issue]) -> Vec<String> {
+    let mut lines: Vec<String> = issues
+        .iter()
+        .take(REPORT_LIMIT)
+        .map(|issue| issue.to_string())
+        .collect();
+    if issues.len() > REPORT_LIMIT {
+        lines.push(format!("...and {} more", issues.len() - REPORT_LIMIT));
+    }
+    lines.push(summary(issues));
+    lines
+}
+
+pub fn summary(issues: &[Issue]) -> String {
+    let count = |severity| {
+        issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    };
+    format!(
+        "{} errors, {} warnings and {} info",
+        count(Severity::Error),
+        count(Severity::Warning),
+        count(Severity::Info)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chart::NoteLane::*,
+        timing::{TimingPoint, DEFAULT_TIMING_POINT},
+    };
+
+    fn chart(notes: Vec<NoteId>) -> Chart {
+        let mut chart = Chart {
+            notes,
+            timing_points: vec![DEFAULT_TIMING_POINT],
+            ..Default::default()
+        };
+        chart.metadata.song = "song.ogg".into();
+        chart
+    }
+
+    fn hold(timing: usize, end: usize) -> NoteId {
+        NoteId {
+            timing,
+            lane: LaneD,
+            end: Some(end),
+        }
+    }
+
+    fn errors(chart: &Chart) -> Vec<String> {
+        validate(chart)
+            .into_iter()
+            .filter(|issue| issue.severity == Severity::Error)
+            .map(|issue| issue.message)
+            .collect()
+    }
+
+    #[test]
+    fn a_playable_chart_has_no_issues() {
+        let chart = chart(vec![
+            NoteId::tap(60, LaneD),
+            NoteId::tap(60, LaneF),
+            hold(90, 120),
+            NoteId::tap(130, LaneD),
+        ]);
+        assert!(validate(&chart).is_empty());
+    }
+
+    #[test]
+    fn bad_bpms_are_errors() {
+        for bpm in [0.0, -120.0, f64::NAN, f64::INFINITY] {
+            let mut chart = chart(vec![NoteId::tap(60, LaneD)]);
+            chart.timing_points[0].bpm = bpm;
+            assert_eq!(errors(&chart).len(), 1, "{}", bpm);
+        }
+    }
+
+    #[test]
+    fn timing_points_must_be_in_order() {
+        let mut chart = chart(vec![NoteId::tap(60, LaneD)]);
+        chart.timing_points.insert(
+            0,
+            TimingPoint {
+                timing: 120.0,
+                ..DEFAULT_TIMING_POINT
+            },
+        );
+        assert_eq!(errors(&chart), ["timing points aren't in order"]);
+    }
+
+    #[test]
+    fn unsorted_notes_are_an_error() {
+        let chart = chart(vec![NoteId::tap(90, LaneD), NoteId::tap(60, LaneF)]);
+        let issues = validate(&chart);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].timing, Some(60));
+    }
+
+    #[test]
+    fn duplicates_and_close_notes() {
+        let chart = chart(vec![
+            NoteId::tap(60, LaneD),
+            NoteId::tap(60, LaneD),
+            NoteId::tap(62, LaneD),
+        ]);
+        let issues = validate(&chart);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].message, "duplicate note in LaneD");
+        assert_eq!(issues[1].severity, Severity::Warning);
+        assert_eq!(issues[1].timing, Some(62));
+    }
+
+    #[test]
+    fn holds_must_end_after_they_start_and_not_overlap() {
+        assert_eq!(errors(&chart(vec![hold(90, 90)])).len(), 1);
+        assert_eq!(
+            errors(&chart(vec![hold(60, 120), NoteId::tap(100, LaneD)])),
+            ["LaneD lands inside the hold before it"]
+        );
+        // other lanes can be played during a hold
+        assert!(errors(&chart(vec![hold(60, 120), NoteId::tap(100, LaneF)])).is_empty());
+    }
+
+    #[test]
+    fn report_is_capped() {
+        let notes = (0..30).map(|_| NoteId::tap(60, LaneD)).collect();
+        let lines = report(&validate(&chart(notes)));
+        assert_eq!(lines.len(), REPORT_LIMIT + 2);
+        assert_eq!(lines.last().unwrap(), "29 errors, 0 warnings and 0 info");
+    }
+}
@@ -0,0 +1,235 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use bevy::{
+    audio::{AddAudioSource, Decodable, Source},
+    prelude::*,
+};
+use bevy_console::{AddConsoleCommand, ConsoleCommand};
+use clap::Parser;
+use machitan_format::{
+    timing::{beat_lines, BeatLine},
+    LANES,
+};
+
+use crate::{
+    editor::EditorPlayback,
+    note::{lane_transforms, Beatmap, LANE_VERT_POS},
+    ApplicationState, FramesCount,
+};
+
+pub struct MetronomePlugin;
+
+impl Plugin for MetronomePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<MetronomeClick>()
+            .insert_resource(Metronome {
+                audible: false,
+                visual: false,
+            })
+            .init_resource::<BeatFlash>()
+            .add_systems(Startup, metronome_setup)
+            .add_systems(
+                Update,
+                (
+                    tick_metronome.run_if(
+                        in_state(ApplicationState::Editor)
+                            .or_else(in_state(ApplicationState::InGame)),
+                    ),
+                    draw_beat_flash.run_if(in_state(ApplicationState::InGame)),
+                )
+                    .chain(),
+            )
+            .add_console_command::<MetronomeCommand, _>(metronome_command);
+    }
+}
+
+// clicks on every beat in the editor, flashes the judgement line in play mode
+#[derive(Resource)]
+pub(crate) struct Metronome {
+    pub audible: bool,
+    pub visual: bool,
+}
+
+#[derive(Resource)]
+struct MetronomeSounds {
+    beat: Handle<MetronomeClick>,
+    downbeat: Handle<MetronomeClick>,
+}
+
+// fades out after each beat, brighter on downbeats
+#[derive(Resource, Default)]
+struct BeatFlash(f32);
+
+// a short sine blip, so the metronome doesn't need any audio files
+#[derive(Asset, TypePath)]
+pub struct MetronomeClick {
+    frequency: f32,
+}
+
+const CLICK_SAMPLE_RATE: u32 = 44_100;
+const CLICK_LENGTH: Duration = Duration::from_millis(40);
+
+pub struct ClickDecoder {
+    frequency: f32,
+    sample: u32,
+    samples: u32,
+}
+
+impl Iterator for ClickDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample >= self.samples {
+            return None;
+        }
+        let t = self.sample as f32 / CLICK_SAMPLE_RATE as f32;
+        let fade = 1.0 - self.sample as f32 / self.samples as f32;
+        self.sample += 1;
+        Some((t * self.frequency * TAU).sin() * fade * 0.5)
+    }
+}
+
+impl Source for ClickDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        CLICK_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(CLICK_LENGTH)
+    }
+}
+
+impl Decodable for MetronomeClick {
+    type DecoderItem = f32;
+    type Decoder = ClickDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        ClickDecoder {
+            frequency: self.frequency,
+            sample: 0,
+            samples: (CLICK_LENGTH.as_secs_f32() * CLICK_SAMPLE_RATE as f32) as u32,
+        }
+    }
+}
+
+fn metronome_setup(mut commands: Commands, mut clicks: ResMut<Assets<MetronomeClick>>) {
+    commands.insert_resource(MetronomeSounds {
+        beat: clicks.add(MetronomeClick { frequency: 1000.0 }),
+        downbeat: clicks.add(MetronomeClick { frequency: 1500.0 }),
+    });
+}
+
+// the clock can jump when scrubbing or restarting, beats aren't replayed for those
+const MAX_TICK_FRAMES: isize = 8;
+
+#[allow(clippy::too_many_arguments)]
+fn tick_metronome(
+    mut commands: Commands,
+    mut last: Local<isize>,
+    frames: Res<FramesCount>,
+    metronome: Res<Metronome>,
+    sounds: Res<MetronomeSounds>,
+    beatmap: Res<Beatmap>,
+    state: Res<State<ApplicationState>>,
+    playback: Res<EditorPlayback>,
+    mut flash: ResMut<BeatFlash>,
+) {
+    // song time, so play mode's lead-in gets counted in too
+    let now = frames.song_time();
+    let from = *last;
+    *last = now;
+    if now <= from || now - from > MAX_TICK_FRAMES {
+        return;
+    }
+
+    // nothing to tick for, beat lines are only worked out when they get used
+    let ticking = match state.get() {
+        ApplicationState::Editor => metronome.audible && playback.playing,
+        ApplicationState::InGame => metronome.visual,
+        _ => false,
+    };
+    if !ticking {
+        return;
+    }
+
+    // beat lines crossed since the last update, a 1/4 snap puts one on every beat
+    let Some((_, kind)) = beat_lines(&beatmap.timing_points, from as f64, now as f64, 4)
+        .into_iter()
+        .find(|(timing, _)| *timing > from as f64)
+    else {
+        return;
+    };
+    let downbeat = kind == BeatLine::Measure;
+
+    match state.get() {
+        ApplicationState::Editor => {
+            let click = if downbeat {
+                &sounds.downbeat
+            } else {
+                &sounds.beat
+            };
+            commands.spawn(AudioSourceBundle {
+                source: click.clone(),
+                settings: PlaybackSettings::DESPAWN,
+            });
+        }
+        ApplicationState::InGame => {
+            flash.0 = if downbeat { 1.0 } else { 0.5 };
+        }
+        _ => {}
+    }
+}
+
+fn draw_beat_flash(mut gizmos: Gizmos, time: Res<Time>, mut flash: ResMut<BeatFlash>) {
+    if flash.0 <= 0.0 {
+        return;
+    }
+
+    let left = lane_transforms(&LANES[0], None).translation.x - 72.;
+    let right = lane_transforms(&LANES[LANES.len() - 1], None).translation.x + 72.;
+    gizmos.line_2d(
+        Vec2::new(left, LANE_VERT_POS),
+        Vec2::new(right, LANE_VERT_POS),
+        Color::WHITE.with_a(flash.0),
+    );
+    flash.0 -= time.delta_seconds() * 4.;
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "metronome")]
+struct MetronomeCommand {
+    // clicks in the editor, toggles when left out
+    #[arg(action = clap::ArgAction::Set)]
+    audible: Option<bool>,
+    // flash the judgement line on every beat while playing
+    #[arg(long, action = clap::ArgAction::Set)]
+    visual: Option<bool>,
+}
+
+fn metronome_command(mut log: ConsoleCommand<MetronomeCommand>, mut metronome: ResMut<Metronome>) {
+    if let Some(Ok(MetronomeCommand { audible, visual })) = log.take() {
+        match (audible, visual) {
+            (None, None) => metronome.audible = !metronome.audible,
+            _ => {
+                if let Some(audible) = audible {
+                    metronome.audible = audible;
+                }
+                if let Some(visual) = visual {
+                    metronome.visual = visual;
+                }
+            }
+        }
+        log.reply(format!(
+            "Metronome clicks {}, flashes {}",
+            metronome.audible, metronome.visual
+        ));
+    }
+}
@@ -1,12 +1,23 @@
-use std::fs::File;
-use std::io::prelude::*;
-
-use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*, render::view::PostProcessWrite};
-use bevy_console::{AddConsoleCommand, ConsoleCommand};
+use bevy::{
+    asset::AssetLoadFailedEvent, diagnostic::FrameTimeDiagnosticsPlugin, prelude::*,
+    render::view::PostProcessWrite, sprite::Anchor,
+};
+use bevy_console::{AddConsoleCommand, ConsoleCommand, PrintConsoleLine};
 use clap::Parser;
-use serde::{Deserialize, Serialize};
-
-use crate::FramesCount;
+use machitan_format::{
+    export::osu::{export_osu, write_osz},
+    file::{read_chart, write_chart},
+    validate::{report, validate, Issue, Severity},
+    Chart, NoteId, NoteLane, FRAMES_TO_TIMING, LANES,
+};
+
+use crate::{
+    beatmap::{asset_path, BeatmapLoader},
+    history::{leaving_unsaved, EditHistory},
+    loading::{GameAssets, LoadingTarget, SelectedChart},
+    song::{frames_to_duration, PlaybackRate, RateScaled, SongClip},
+    ApplicationState, FramesCount,
+};
 
 pub struct NotePlugin;
 
@@ -15,30 +26,66 @@ pub struct NoteSet;
 
 impl Plugin for NotePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, (spawn_note_frames).in_set(NoteSet))
-            .add_systems(FixedUpdate, (spawn_note, animate_note).in_set(NoteSet))
-            .insert_resource(Beatmap {
-                notes: SAMPLE_BEATMAP.into(),
-            })
-            .add_console_command::<SaveCommand, _>(save_command)
-            .add_console_command::<ReloadCommand, _>(reload_command)
-            .add_console_command::<LoadCommand, _>(load_command);
+        app.add_systems(
+            OnEnter(ApplicationState::InGame),
+            (spawn_note_frames, apply_chart, start_song).chain(),
+        )
+        .add_systems(OnExit(ApplicationState::InGame), clear_playfield)
+        .add_systems(
+            Update,
+            (
+                reload_chart.run_if(in_state(ApplicationState::InGame)),
+                report_chart_errors,
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            (spawn_note, animate_note, animate_holds, play_keysounds).in_set(NoteSet),
+        )
+        .insert_resource(Beatmap(Chart {
+            notes: SAMPLE_BEATMAP.into(),
+            ..default()
+        }))
+        .insert_resource(BeatmapCursor {
+            next: 0,
+            keysound: 0,
+        })
+        .insert_resource(WatchChart(true))
+        .init_asset::<Beatmap>()
+        .init_asset_loader::<BeatmapLoader>()
+        .add_console_command::<SaveCommand, _>(save_command)
+        .add_console_command::<ExportCommand, _>(export_command)
+        .add_console_command::<ReloadCommand, _>(reload_command)
+        .add_console_command::<LoadCommand, _>(load_command)
+        .add_console_command::<WatchCommand, _>(watch_command)
+        .add_console_command::<ValidateCommand, _>(validate_command);
     }
 }
 
 #[derive(Component)]
 pub struct NoteTag;
 
-#[derive(Component, Clone, Deserialize, Serialize, Debug)]
-pub struct NoteId {
-    pub timing: usize,
-    pub lane: NoteLane,
-}
+#[derive(Component)]
+struct SongTag;
+
+// the chart note a spawned note entity plays
+#[derive(Component, Deref, Debug)]
+pub struct ChartNote(pub NoteId);
+
+// a hold whose head was hit and whose key is still down
+#[derive(Component)]
+pub(crate) struct Held;
+
+// the stretched part of a hold, a child of its head
+#[derive(Component)]
+struct HoldBody;
 
 // lane consts
 // notes are 128 px wide + 16px boundary
 // Y positions
-const LANE_VERT_POS: f32 = -275.0; // Y position of lanes
+pub(crate) const LANE_VERT_POS: f32 = -275.0; // Y position of lanes
+const NOTE_SPEED: f32 = 600.0; // px per second notes fall at
+const HOLD_WIDTH: f32 = 64.0;
 
 // X positions
 const LANE_A_POS: f32 = -525.0;
@@ -50,56 +97,54 @@ const LANE_K_POS: f32 = 237.0;
 const LANE_L_POS: f32 = 381.0;
 const LANE_SEMI_POS: f32 = 525.0;
 
-#[derive(Component, Clone, PartialEq, Eq, Copy, Serialize, Deserialize, Debug)]
-pub enum NoteLane {
-    LaneA,
-    LaneS,
-    LaneD,
-    LaneF,
-    LaneJ,
-    LaneK,
-    LaneL,
-    LaneSemicolon,
-}
-
 #[derive(Bundle)]
 struct Note {
     tag: NoteTag,
-    id: NoteId,
+    id: ChartNote,
     sprite: SpriteBundle,
 }
 
 #[derive(Component)]
 struct FrameTag;
 
+#[derive(Component, Deref)]
+struct FrameLane(NoteLane);
+
 #[derive(Bundle)]
 struct Frame {
     tag: FrameTag,
-    lane: NoteLane,
+    lane: FrameLane,
     sprite: SpriteBundle,
 }
 
-const LANES: [NoteLane; 8] = [
-    NoteLane::LaneA,
-    NoteLane::LaneS,
-    NoteLane::LaneD,
-    NoteLane::LaneF,
-    NoteLane::LaneJ,
-    NoteLane::LaneK,
-    NoteLane::LaneL,
-    NoteLane::LaneSemicolon,
+// keys are the lane names, the same ones played with, in the order of LANES
+const LANE_KEYS: [KeyCode; 8] = [
+    KeyCode::KeyA,
+    KeyCode::KeyS,
+    KeyCode::KeyD,
+    KeyCode::KeyF,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::Semicolon,
 ];
 
-const FRAMES_TO_TIMING: usize = 56;
+pub(crate) fn lane_for_key(key: &KeyCode) -> Option<NoteLane> {
+    let index = LANE_KEYS.iter().position(|k| k == key)?;
+    Some(LANES[index])
+}
+
+// how far off a tap can be from a note's timing and still hit it
+pub(crate) const HIT_WINDOW: isize = 10;
 
-fn spawn_note_frames(mut commands: Commands, server: Res<AssetServer>) {
+fn spawn_note_frames(mut commands: Commands, assets: Res<GameAssets>) {
     for lane in LANES.iter() {
-        let frame_sprite: Handle<Image> = server.load("note_frame.png");
+        let frame_sprite = assets.note_frame.clone();
 
         let transform = lane_transforms(lane, Some(LANE_VERT_POS));
         let frame = Frame {
             tag: FrameTag,
-            lane: lane.to_owned(),
+            lane: FrameLane(*lane),
             sprite: SpriteBundle {
                 texture: frame_sprite,
                 transform,
@@ -111,63 +156,309 @@ fn spawn_note_frames(mut commands: Commands, server: Res<AssetServer>) {
     }
 }
 
-fn spawn_note(
+fn apply_chart(
+    assets: Res<GameAssets>,
+    selected: Res<SelectedChart>,
+    charts: Res<Assets<Beatmap>>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+) {
+    if let Some(chart) = assets.chart.as_ref().and_then(|handle| charts.get(handle)) {
+        *beatmap = chart.clone();
+        history.clear();
+        // remember which song it was played with so saving keeps it
+        if beatmap.metadata.song.is_empty() {
+            if let Some(song) = &selected.song {
+                beatmap.metadata.song = song.clone();
+            }
+        }
+    }
+}
+
+// whether saving the chart file reloads it while playing
+#[derive(Resource)]
+pub struct WatchChart(pub bool);
+
+// picks up edits to the chart file on disk without touching the song position,
+// notes behind the playhead are left alone and everything ahead is respawned
+fn reload_chart(
     mut commands: Commands,
-    server: Res<AssetServer>,
+    mut ev_asset: EventReader<AssetEvent<Beatmap>>,
+    mut console: EventWriter<PrintConsoleLine>,
+    watch: Res<WatchChart>,
+    assets: Res<GameAssets>,
+    charts: Res<Assets<Beatmap>>,
     frames: Res<FramesCount>,
     mut beatmap: ResMut<Beatmap>,
+    mut cursor: ResMut<BeatmapCursor>,
+    mut history: ResMut<EditHistory>,
+    note_query: Query<(Entity, &ChartNote), With<NoteTag>>,
 ) {
-    if !beatmap.notes.is_empty() {
-        let note_sprite: Handle<Image> = server.load("sq_note.png");
-        let transform = lane_transforms(&beatmap.notes.first().unwrap().lane, None);
-        match &mut *beatmap.notes {
-            [head, tail @ ..] => {
-                if frames.count >= head.timing - FRAMES_TO_TIMING {
-                    let note = Note {
-                        tag: NoteTag,
-                        id: head.clone(),
-                        sprite: SpriteBundle {
-                            texture: note_sprite,
-                            transform,
+    let Some(handle) = &assets.chart else {
+        return;
+    };
+
+    for ev in ev_asset.read() {
+        if !watch.0 || !ev.is_modified(handle) {
+            continue;
+        }
+        let Some(chart) = charts.get(handle) else {
+            continue;
+        };
+        let issues = validate(chart);
+        if has_errors(&issues) {
+            for line in report(&issues) {
+                console.send(PrintConsoleLine::new(line.into()));
+            }
+            console.send(PrintConsoleLine::new(
+                "Kept playing the last good version of the chart".into(),
+            ));
+            continue;
+        }
+
+        for (entity, note_id) in note_query.iter() {
+            if note_id.timing as isize > frames.song_time() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        *beatmap = chart.clone();
+        history.clear();
+        cursor.next = beatmap
+            .notes
+            .iter()
+            .position(|note| note.timing as isize > frames.song_time())
+            .unwrap_or(beatmap.notes.len());
+        cursor.keysound = beatmap
+            .keysounds
+            .iter()
+            .position(|keysound| keysound.timing as isize > frames.song_time())
+            .unwrap_or(beatmap.keysounds.len());
+
+        console.send(PrintConsoleLine::new(
+            format!(
+                "Reloaded chart at frame {}, {} notes ahead",
+                frames.song_time(),
+                beatmap.notes.len() - cursor.next
+            )
+            .into(),
+        ));
+    }
+}
+
+fn has_errors(issues: &[Issue]) -> bool {
+    issues.iter().any(|issue| issue.severity == Severity::Error)
+}
+
+// a chart that fails to parse keeps its last good version, so just tell the charter why
+fn report_chart_errors(
+    mut ev_failed: EventReader<AssetLoadFailedEvent<Beatmap>>,
+    mut console: EventWriter<PrintConsoleLine>,
+) {
+    for ev in ev_failed.read() {
+        console.send(PrintConsoleLine::new(
+            format!("Failed to load {}: {}", ev.path, ev.error).into(),
+        ));
+    }
+}
+
+// silence before the song so notes near its start still get to scroll in
+fn lead_in(beatmap: &Beatmap) -> usize {
+    let first = beatmap.notes.iter().map(|note| note.timing).min();
+    FRAMES_TO_TIMING.saturating_sub(first.unwrap_or(FRAMES_TO_TIMING))
+}
+
+fn spawn_song(
+    commands: &mut Commands,
+    assets: &GameAssets,
+    songs: &Assets<AudioSource>,
+    clips: &mut Assets<SongClip>,
+    rate: &PlaybackRate,
+    lead_in: usize,
+) {
+    if let Some(song) = assets.song.as_ref().and_then(|handle| songs.get(handle)) {
+        commands.spawn((
+            SongTag,
+            RateScaled,
+            AudioSourceBundle {
+                source: clips.add(SongClip::with_lead_in(song, frames_to_duration(lead_in))),
+                settings: rate.settings(PlaybackSettings::ONCE),
+            },
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_song(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    songs: Res<Assets<AudioSource>>,
+    mut clips: ResMut<Assets<SongClip>>,
+    beatmap: Res<Beatmap>,
+    mut frames: ResMut<FramesCount>,
+    mut cursor: ResMut<BeatmapCursor>,
+    rate: Res<PlaybackRate>,
+) {
+    frames.count = 0;
+    frames.lead_in = lead_in(&beatmap);
+    spawn_song(
+        &mut commands,
+        &assets,
+        &songs,
+        &mut clips,
+        &rate,
+        frames.lead_in,
+    );
+    cursor.next = 0;
+    cursor.keysound = 0;
+}
+
+fn clear_playfield(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<NoteTag>, With<FrameTag>, With<SongTag>)>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_note(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    frames: Res<FramesCount>,
+    beatmap: Res<Beatmap>,
+    mut cursor: ResMut<BeatmapCursor>,
+) {
+    // several notes can be due at once, e.g. right after a chart reload
+    while let Some(head) = beatmap.notes.get(cursor.next) {
+        if frames.song_time() < head.timing as isize - FRAMES_TO_TIMING as isize {
+            break;
+        }
+
+        let transform = lane_transforms(
+            &head.lane,
+            Some(note_height(head.timing, frames.song_time())),
+        );
+        let note = Note {
+            tag: NoteTag,
+            id: ChartNote(head.clone()),
+            sprite: SpriteBundle {
+                texture: assets.note.clone(),
+                transform,
+                ..default()
+            },
+        };
+
+        let mut entity = commands.spawn(note);
+        if head.end.is_some() {
+            entity.with_children(|parent| {
+                parent.spawn((
+                    HoldBody,
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::rgba(1., 1., 1., 0.6),
+                            anchor: Anchor::BottomCenter,
                             ..default()
                         },
-                    };
+                        // behind the head
+                        transform: Transform::from_xyz(0., 0., -1.),
+                        ..default()
+                    },
+                ));
+            });
+        }
 
-                    commands.spawn(note);
+        cursor.next += 1;
+    }
+}
 
-                    beatmap.notes = tail.into();
-                }
-            }
-            _ => unreachable!(),
+// keysounds without a lane are the song's background, the others play when their note is hit
+fn play_keysounds(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    frames: Res<FramesCount>,
+    beatmap: Res<Beatmap>,
+    rate: Res<PlaybackRate>,
+    mut cursor: ResMut<BeatmapCursor>,
+) {
+    while let Some(keysound) = beatmap.keysounds.get(cursor.keysound) {
+        if keysound.timing as isize > frames.song_time() {
+            break;
+        }
+        cursor.keysound += 1;
+
+        if keysound.lane.is_some() {
+            continue;
+        }
+        if let Some(sample) = assets.keysounds.get(&keysound.sample) {
+            commands.spawn((
+                RateScaled,
+                AudioSourceBundle {
+                    source: sample.clone(),
+                    settings: rate.settings(PlaybackSettings::DESPAWN),
+                },
+            ));
         }
     }
 }
 
+// height a note should be at so it reaches the lane frame exactly on its timing
+fn note_height(timing: usize, song_time: isize) -> f32 {
+    let remaining = (timing as isize - song_time).max(0) as f32;
+    (LANE_VERT_POS + remaining * NOTE_SPEED / 60.).min(-LANE_VERT_POS)
+}
+
 fn animate_note(
     mut commands: Commands,
     time: Res<Time>,
     frames: Res<FramesCount>,
-    mut query: Query<(&mut Transform, Entity, &NoteId), With<NoteTag>>,
+    mut query: Query<(&mut Transform, Entity, &ChartNote, Has<Held>), With<NoteTag>>,
 ) {
-    for (mut position, entity, note_id) in query.iter_mut() {
-        let translate = 600. * time.delta_seconds();
+    for (mut position, entity, note_id, held) in query.iter_mut() {
+        let translate = NOTE_SPEED * time.delta_seconds();
 
         if position.translation.y <= LANE_VERT_POS {
             position.translation.y = LANE_VERT_POS;
-            println!("{}", frames.count);
             // position.translation.y -= translate;
         } else {
             position.translation.y -= translate;
         }
 
-        if frames.count > note_id.timing + 10 {
-            println!("despawn  at {} for {:?}", frames.count, &note_id);
-            commands.entity(entity).despawn();
+        // held notes wait on the lane frame until the hold is over
+        let last = if held {
+            note_id.last_frame()
+        } else {
+            note_id.timing
+        };
+        if frames.song_time() > last as isize + HIT_WINDOW {
+            debug!("despawn at {} for {:?}", frames.count, note_id);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// hold bodies run from their head up to where the hold ends, shrinking while it's held
+fn animate_holds(
+    frames: Res<FramesCount>,
+    notes: Query<(&Transform, &ChartNote, &Children), With<NoteTag>>,
+    mut bodies: Query<&mut Sprite, With<HoldBody>>,
+) {
+    for (position, note_id, children) in notes.iter() {
+        let Some(end) = note_id.end else {
+            continue;
+        };
+        // not capped like note_height, long holds run off the top of the screen
+        let remaining = (end as isize - frames.song_time()).max(0) as f32;
+        let tail = LANE_VERT_POS + remaining * NOTE_SPEED / 60.;
+        let length = (tail - position.translation.y).max(0.);
+        for child in children.iter() {
+            if let Ok(mut sprite) = bodies.get_mut(*child) {
+                sprite.custom_size = Some(Vec2::new(HOLD_WIDTH, length));
+            }
         }
     }
 }
 
-fn lane_transforms(lane: &NoteLane, y_pos: Option<f32>) -> Transform {
+pub(crate) fn lane_transforms(lane: &NoteLane, y_pos: Option<f32>) -> Transform {
     let y = if let Some(pos) = y_pos {
         pos
     } else {
@@ -189,19 +480,65 @@ fn lane_transforms(lane: &NoteLane, y_pos: Option<f32>) -> Transform {
 #[derive(ConsoleCommand, Parser)]
 #[command(name = "save")]
 struct SaveCommand {
-    dest: String,
+    // relative to the assets folder like `load`, the loaded chart's file when left out
+    dest: Option<String>,
 }
 
-fn save_command(mut log: ConsoleCommand<SaveCommand>, beatmap: Res<Beatmap>) {
+fn save_command(
+    mut log: ConsoleCommand<SaveCommand>,
+    beatmap: Res<Beatmap>,
+    selected: Res<SelectedChart>,
+    mut history: ResMut<EditHistory>,
+) {
     if let Some(Ok(SaveCommand { dest })) = log.take() {
-        let mut file = File::create(&dest).unwrap();
-        // let toml_beatmap = toml::to_string(beatmap.as_ref()).unwrap();
-        let toml_beatmap = toml::to_string(&Beatmap {
-            notes: SAMPLE_BEATMAP.into(),
-        })
-        .unwrap();
-        file.write_all(toml_beatmap.as_bytes()).unwrap();
-        log.reply(format!("Saved current beatmap as {}", dest));
+        let Some(dest) = dest.or(selected.chart.clone()) else {
+            log.reply("No chart file is loaded, give `save` a path");
+            return;
+        };
+        match write_chart(&asset_path(&dest), &beatmap.0) {
+            Ok(()) => {
+                history.dirty = false;
+                log.reply(format!("Saved current beatmap as {}", dest));
+                for line in report(&validate(&beatmap)) {
+                    log.reply(line);
+                }
+            }
+            Err(e) => log.reply(format!("Could not save beatmap: {}", e)),
+        }
+    }
+}
+
+// osu!mania beatmap, packed with the song and keysounds when dest ends in .osz
+#[derive(ConsoleCommand, Parser)]
+#[command(name = "export")]
+struct ExportCommand {
+    dest: String,
+}
+
+fn export_command(mut log: ConsoleCommand<ExportCommand>, beatmap: Res<Beatmap>) {
+    if let Some(Ok(ExportCommand { dest })) = log.take() {
+        let export = match export_osu(&beatmap, None) {
+            Ok(export) => export,
+            Err(e) => {
+                log.reply(format!("Could not export beatmap: {}", e));
+                return;
+            }
+        };
+        let path = asset_path(&dest);
+        let written = if dest.to_lowercase().ends_with(".osz") {
+            write_osz(&path, &export, asset_path).map_err(|e| e.to_string())
+        } else {
+            std::fs::write(&path, &export.text).map_err(|e| e.to_string())
+        };
+        match written {
+            Ok(()) => {
+                log.reply(format!("Exported current beatmap as {}", dest));
+                for warning in export.warnings {
+                    log.reply(warning);
+                }
+            }
+            Err(e) => log.reply(format!("Could not export beatmap: {}", e)),
+        }
     }
 }
 
@@ -209,14 +546,41 @@ fn save_command(mut log: ConsoleCommand<SaveCommand>, beatmap: Res<Beatmap>) {
 #[command(name = "reload")]
 struct ReloadCommand;
 
+#[allow(clippy::too_many_arguments)]
 fn reload_command(
+    mut commands: Commands,
     mut log: ConsoleCommand<ReloadCommand>,
-    mut beatmap: ResMut<Beatmap>,
+    assets: Res<GameAssets>,
+    songs: Res<Assets<AudioSource>>,
+    mut clips: ResMut<Assets<SongClip>>,
+    beatmap: Res<Beatmap>,
+    mut cursor: ResMut<BeatmapCursor>,
     mut frames: ResMut<FramesCount>,
+    rate: Res<PlaybackRate>,
+    query: Query<Entity, Or<(With<NoteTag>, With<SongTag>)>>,
+    state: Res<State<ApplicationState>>,
 ) {
     if let Some(Ok(ReloadCommand)) = log.take() {
-        beatmap.notes = SAMPLE_BEATMAP.into();
+        // restarts the song and notes of the chart being played, there are none elsewhere
+        if *state.get() != ApplicationState::InGame {
+            log.reply("reload only works while playing a chart");
+            return;
+        }
+        for entity in query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
         frames.count = 0;
+        frames.lead_in = lead_in(&beatmap);
+        spawn_song(
+            &mut commands,
+            &assets,
+            &songs,
+            &mut clips,
+            &rate,
+            frames.lead_in,
+        );
+        cursor.next = 0;
+        cursor.keysound = 0;
         log.reply("Reloaded!");
     }
 }
@@ -225,70 +589,182 @@ fn reload_command(
 #[command(name = "load")]
 struct LoadCommand {
     file: String,
-    song: String,
+    // defaults to the song named in the chart's metadata
+    song: Option<String>,
+    // load even with unsaved edits in the editor
+    #[arg(long)]
+    force: bool,
 }
 
 fn load_command(
-    mut commands: Commands,
     mut log: ConsoleCommand<LoadCommand>,
-    mut beatmap: ResMut<Beatmap>,
-    mut frames: ResMut<FramesCount>,
-    server: Res<AssetServer>,
+    mut selected: ResMut<SelectedChart>,
+    mut target: ResMut<LoadingTarget>,
+    state: Res<State<ApplicationState>>,
+    history: Res<EditHistory>,
+    mut next_state: ResMut<NextState<ApplicationState>>,
 ) {
-    if let Some(Ok(LoadCommand { file, song })) = log.take() {
-        let mut load_file = File::open(file).unwrap();
-        let mut load_beatmap = String::new();
-        load_file.read_to_string(&mut load_beatmap).unwrap();
-        let new_beatmap: Beatmap = toml::from_str(&load_beatmap).unwrap();
+    if let Some(Ok(LoadCommand { file, song, force })) = log.take() {
+        if !force && leaving_unsaved(&state, &history) {
+            log.reply("The chart has unsaved changes, `save` it first or use --force");
+            return;
+        }
 
-        let new_song: Handle<AudioSource> = server.load(song);
+        // check the files up front so a bad path doesn't leave us stuck loading
+        let chart = match read_chart(&asset_path(&file)) {
+            Ok(chart) => chart,
+            Err(e) => {
+                log.reply(format!("Could not load beatmap: {}", e));
+                return;
+            }
+        };
+        let song = song.or(Some(chart.metadata.song.clone()).filter(|s| !s.is_empty()));
+        // keysounded charts can have their whole song in keysounds
+        if song.is_none() && chart.keysounds.is_empty() {
+            log.reply(format!("No song given and {} doesn't name one", file));
+            return;
+        }
+        if let Some(song) = song.as_ref().filter(|song| !asset_path(song).is_file()) {
+            log.reply(format!("Could not load song: {} not found", song));
+            return;
+        }
+        let mut keysounds: Vec<String> = chart
+            .keysounds
+            .iter()
+            .map(|keysound| keysound.sample.clone())
+            .collect();
+        keysounds.sort();
+        keysounds.dedup();
+        let missing = keysounds
+            .iter()
+            .filter(|sample| !asset_path(sample).is_file())
+            .count();
+        if missing > 0 {
+            log.reply(format!(
+                "{} of {} keysound samples weren't found and will be silent",
+                missing,
+                keysounds.len()
+            ));
+        }
+        // out of order notes would stall the note spawner, so broken charts don't get played
+        let issues = validate(&chart);
+        for line in report(&issues) {
+            log.reply(line);
+        }
+        if has_errors(&issues) {
+            log.reply(format!("Not playing {} until its errors are fixed", file));
+            return;
+        }
 
-        commands.spawn(AudioBundle {
-            source: new_song,
-            ..default()
-        });
-        beatmap.notes = new_beatmap.notes;
-        frames.count = 0;
+        // chart, song and skin get loaded in ApplicationState::Loading, which starts the chart when done
+        selected.chart = Some(file);
+        selected.song = song;
+        selected.keysounds = keysounds;
+        target.0 = ApplicationState::InGame;
+        next_state.set(ApplicationState::Loading);
     }
 }
 
-#[derive(Resource, Serialize, Deserialize)]
-pub struct Beatmap {
-    // filename: String,
-    pub notes: Vec<NoteId>,
+#[derive(ConsoleCommand, Parser)]
+#[command(name = "watch")]
+struct WatchCommand {
+    // "on" or "off", toggles when left out
+    mode: Option<String>,
+}
+
+fn watch_command(mut log: ConsoleCommand<WatchCommand>, mut watch: ResMut<WatchChart>) {
+    if let Some(Ok(WatchCommand { mode })) = log.take() {
+        watch.0 = match mode.as_deref() {
+            Some("on") => true,
+            Some("off") => false,
+            None => !watch.0,
+            Some(other) => {
+                log.reply(format!("Unknown watch mode {}, expected on or off", other));
+                return;
+            }
+        };
+        log.reply(format!(
+            "Chart watch mode {}",
+            if watch.0 { "on" } else { "off" }
+        ));
+    }
+}
+
+// the loaded chart, left untouched while playing so it can be saved or restarted
+#[derive(Resource, Asset, TypePath, Clone, Deref, DerefMut)]
+pub struct Beatmap(pub Chart);
+
+// index of the next note in the Beatmap to spawn and the next keysound to play
+#[derive(Resource)]
+pub struct BeatmapCursor {
+    pub next: usize,
+    pub keysound: usize,
 }
 
 const SAMPLE_BEATMAP: [NoteId; 8] = [
     NoteId {
         timing: 200,
         lane: NoteLane::LaneA,
+        end: None,
     },
     NoteId {
         timing: 250,
         lane: NoteLane::LaneS,
+        end: None,
     },
     NoteId {
         timing: 300,
         lane: NoteLane::LaneD,
+        end: None,
     },
     NoteId {
         timing: 350,
         lane: NoteLane::LaneF,
+        end: None,
     },
     NoteId {
         timing: 400,
         lane: NoteLane::LaneJ,
+        end: None,
     },
     NoteId {
         timing: 450,
         lane: NoteLane::LaneK,
+        end: None,
     },
     NoteId {
         timing: 500,
         lane: NoteLane::LaneL,
+        end: None,
     },
     NoteId {
         timing: 550,
         lane: NoteLane::LaneSemicolon,
+        end: None,
     },
 ];
+
+#[derive(ConsoleCommand, Parser)]
+#[command(name = "validate")]
+struct ValidateCommand {
+    // checks the chart being played or edited when left out
+    file: Option<String>,
+}
+
+fn validate_command(mut log: ConsoleCommand<ValidateCommand>, beatmap: Res<Beatmap>) {
+    if let Some(Ok(ValidateCommand { file })) = log.take() {
+        let issues = match file {
+            Some(file) => match read_chart(&asset_path(&file)) {
+                Ok(chart) => validate(&chart),
+                Err(e) => {
+                    log.reply(format!("Could not load beatmap: {}", e));
+                    return;
+                }
+            },
+            None => validate(&beatmap),
+        };
+        for line in report(&issues) {
+            log.reply(line);
+        }
+    }
+}
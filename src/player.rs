@@ -1,8 +1,11 @@
 use bevy::{input::keyboard::KeyCode, prelude::*};
+use bevy_console::ConsoleOpen;
+use machitan_format::NoteLane;
 
 use crate::{
-    note::{NoteId, NoteLane, NoteTag},
-    FramesCount, PauseState,
+    loading::GameAssets,
+    note::{lane_for_key, Beatmap, ChartNote, Held, NoteTag, HIT_WINDOW},
+    ApplicationState, FramesCount, PauseState,
 };
 
 pub struct PlayerPlugin;
@@ -12,11 +15,26 @@ pub struct PlayerSet;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, (player_setup, ganbaru_mun))
-            .add_systems(Update, (player_input, play_mun, lane_tap, pause))
-            .add_event::<MunIdEvent>()
-            .add_event::<LaneTapEvent>()
-            .add_event::<PauseEvent>();
+        app.add_systems(
+            OnEnter(ApplicationState::Menu),
+            (player_setup, ganbaru_mun).run_if(run_once()),
+        )
+        .add_systems(OnEnter(ApplicationState::InGame), unpause)
+        .add_systems(
+            Update,
+            (
+                player_input,
+                play_mun,
+                lane_tap,
+                lane_release,
+                // the editor uses space for playback, so pausing is only for songs
+                (pause_with_console, pause).run_if(in_state(ApplicationState::InGame)),
+            ),
+        )
+        .add_event::<MunIdEvent>()
+        .add_event::<LaneTapEvent>()
+        .add_event::<LaneReleaseEvent>()
+        .add_event::<PauseEvent>();
     }
 }
 
@@ -37,16 +55,16 @@ struct PlayerBundle {
 
 // player specific systems
 
-fn ganbaru_mun(mut commands: Commands, server: Res<AssetServer>) {
+fn ganbaru_mun(mut commands: Commands, assets: Res<GameAssets>) {
     commands.spawn(AudioBundle {
-        source: server.load("ganbaru.ogg"),
+        source: assets.ganbaru.clone(),
         ..default()
     });
 }
 
-fn player_setup(mut commands: Commands, server: Res<AssetServer>) {
+fn player_setup(mut commands: Commands, assets: Res<GameAssets>) {
     println!("player setup");
-    let player_sprite: Handle<Image> = server.load("machitan.png");
+    let player_sprite = assets.player.clone();
     let player = PlayerBundle {
         tag: PlayerTag,
         sprite: SpriteBundle {
@@ -64,9 +82,16 @@ fn player_input(
     time: Res<Time>,
     // mut ev_play_mun: EventWriter<MunIdEvent>,
     mut ev_lane_tap: EventWriter<LaneTapEvent>,
+    mut ev_lane_release: EventWriter<LaneReleaseEvent>,
     mut ev_pause: EventWriter<PauseEvent>,
     mut query: Query<(&mut Transform, Entity, &Pause), With<PlayerTag>>,
+    console: Res<ConsoleOpen>,
 ) {
+    // keys typed into the console aren't taps
+    if console.open {
+        return;
+    }
+
     for (mut position, _entity, pause) in query.iter_mut() {
         let translate = 250. * time.delta_seconds();
 
@@ -111,8 +136,11 @@ fn player_input(
             if key == &KeyCode::Enter {
                 //              ev_lane_tap.send(LaneTapEvent(0));
             }
-            if key == &KeyCode::Backquote {
-                ev_pause.send(PauseEvent);
+        }
+
+        for key in keys.get_just_released() {
+            if let Some(lane) = lane_for_key(key) {
+                ev_lane_release.send(LaneReleaseEvent(lane));
             }
         }
     }
@@ -120,25 +148,21 @@ fn player_input(
 
 // player specific events
 
+// the hit note's keysound when it has one, the lane's hitsound otherwise
 #[derive(Event)]
-struct MunIdEvent(NoteLane);
+struct MunIdEvent(NoteLane, Option<String>);
 
 fn play_mun(
     mut ev_play_mun: EventReader<MunIdEvent>,
     mut commands: Commands,
-    server: Res<AssetServer>,
+    assets: Res<GameAssets>,
 ) {
     for ev in ev_play_mun.read() {
-        let mun = server.load(match ev.0 {
-            NoteLane::LaneA => "mun1.ogg",
-            NoteLane::LaneS => "mun2.ogg",
-            NoteLane::LaneD => "mun3.ogg",
-            NoteLane::LaneF => "mun4.ogg",
-            NoteLane::LaneJ => "mun5.ogg",
-            NoteLane::LaneK => "mun6.ogg",
-            NoteLane::LaneL => "mun7.ogg",
-            NoteLane::LaneSemicolon => "mun8.ogg",
-        });
+        let mun =
+            ev.1.as_ref()
+                .and_then(|keysound| assets.keysounds.get(keysound))
+                .cloned()
+                .unwrap_or_else(|| assets.hitsound(&ev.0));
 
         commands.spawn(AudioBundle {
             source: mun,
@@ -154,17 +178,41 @@ fn lane_tap(
     mut ev_lane_tap: EventReader<LaneTapEvent>,
     mut commands: Commands,
     frames: Res<FramesCount>,
-    mut note_query: Query<(Entity, &NoteId), With<NoteTag>>,
+    beatmap: Res<Beatmap>,
+    mut note_query: Query<(Entity, &ChartNote), (With<NoteTag>, Without<Held>)>,
     mut ev_play_mun: EventWriter<MunIdEvent>,
 ) {
     for ev in ev_lane_tap.read() {
         for (entity, note_id) in note_query.iter_mut() {
-            if frames.count >= note_id.timing - 10
-                && frames.count <= note_id.timing + 10
+            if (frames.song_time() - note_id.timing as isize).abs() <= HIT_WINDOW
                 && ev.0 == note_id.lane
             {
-                commands.entity(entity).despawn();
-                ev_play_mun.send(MunIdEvent(ev.0));
+                // holds stay until their key is let go
+                if note_id.end.is_some() {
+                    commands.entity(entity).insert(Held);
+                } else {
+                    commands.entity(entity).despawn_recursive();
+                }
+                let keysound = beatmap.keysound(note_id).map(str::to_owned);
+                ev_play_mun.send(MunIdEvent(ev.0, keysound));
+            }
+        }
+    }
+}
+
+#[derive(Event)]
+struct LaneReleaseEvent(NoteLane);
+
+// letting go ends a hold, there's no score yet for an early release to cost anything
+fn lane_release(
+    mut ev_lane_release: EventReader<LaneReleaseEvent>,
+    mut commands: Commands,
+    held_query: Query<(Entity, &ChartNote), With<Held>>,
+) {
+    for ev in ev_lane_release.read() {
+        for (entity, note_id) in held_query.iter() {
+            if note_id.lane == ev.0 {
+                commands.entity(entity).despawn_recursive();
             }
         }
     }
@@ -173,6 +221,22 @@ fn lane_tap(
 #[derive(Event)]
 struct PauseEvent;
 
+// every song starts unpaused, whatever happened to the last one
+fn unpause(mut next_state: ResMut<NextState<PauseState>>) {
+    next_state.set(PauseState::Unpaused);
+}
+
+// the song waits while the console is open, also when it was opened before the song started
+fn pause_with_console(console: Res<ConsoleOpen>, mut next_state: ResMut<NextState<PauseState>>) {
+    if console.is_changed() {
+        next_state.set(if console.open {
+            PauseState::Paused
+        } else {
+            PauseState::Unpaused
+        });
+    }
+}
+
 fn pause(
     mut ev_pause: EventReader<PauseEvent>,
     state: Res<State<PauseState>>,
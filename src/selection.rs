@@ -0,0 +1,285 @@
+use bevy::prelude::*;
+use bevy_console::{AddConsoleCommand, ConsoleCommand};
+use clap::{Parser, Subcommand};
+use machitan_format::{NoteId, LANES};
+
+use crate::{
+    editor::{console_closed, EditorSet},
+    history::{ChartEdit, EditHistory},
+    note::Beatmap,
+    timeline::TimelineSelection,
+    FramesCount,
+};
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Clipboard>()
+            .add_systems(
+                Update,
+                selection_hotkeys.run_if(console_closed).in_set(EditorSet),
+            )
+            .add_console_command::<SelectionCommand, _>(selection_command.in_set(EditorSet));
+    }
+}
+
+// copied notes, timed from the first one so they can be pasted anywhere
+#[derive(Resource, Default)]
+struct Clipboard {
+    notes: Vec<NoteId>,
+}
+
+#[derive(Subcommand)]
+enum SelectionAction {
+    // selects every note between two frames
+    Select {
+        from: usize,
+        to: usize,
+    },
+    Copy,
+    Cut,
+    // pastes the copied notes at the playhead
+    Paste,
+    Delete,
+    // flips the selection left to right
+    Mirror,
+    // moves the selection this many lanes to the right, negative for left
+    Shift {
+        #[arg(allow_negative_numbers = true)]
+        lanes: isize,
+    },
+    // spaces the selection out from its first note, 2 for twice as long
+    Stretch {
+        factor: f64,
+    },
+}
+
+fn mirrored(notes: &[NoteId]) -> Vec<NoteId> {
+    notes
+        .iter()
+        .map(|note| note.moved(note.timing, LANES[LANES.len() - 1 - note.lane.index()]))
+        .collect()
+}
+
+// None when any note would end up outside the lanes
+fn shifted(notes: &[NoteId], lanes: isize) -> Option<Vec<NoteId>> {
+    notes
+        .iter()
+        .map(|note| {
+            let lane = note.lane.index() as isize + lanes;
+            let lane = LANES.get(usize::try_from(lane).ok()?)?;
+            Some(note.moved(note.timing, *lane))
+        })
+        .collect()
+}
+
+// holds get stretched along with the gaps between notes, None when any note would end up
+// past the last frame there is
+fn stretched(notes: &[NoteId], factor: f64) -> Option<Vec<NoteId>> {
+    let anchor = notes.iter().map(|note| note.timing).min().unwrap_or(0);
+    let stretch = |timing: usize| {
+        let offset = ((timing - anchor) as f64 * factor).round();
+        // casting saturates instead of failing, so catch offsets that don't fit first
+        if offset >= usize::MAX as f64 {
+            return None;
+        }
+        anchor.checked_add(offset as usize)
+    };
+    notes
+        .iter()
+        .map(|note| {
+            Some(NoteId {
+                timing: stretch(note.timing)?,
+                lane: note.lane,
+                end: match note.end {
+                    Some(end) => Some(stretch(end)?),
+                    None => None,
+                },
+            })
+        })
+        .collect()
+}
+
+// replaces the selected notes with their transformed versions, which stay selected.
+// Returns how many of them were merged into notes already in the chart
+fn transform(
+    label: &'static str,
+    beatmap: &mut Beatmap,
+    history: &mut EditHistory,
+    selection: &mut TimelineSelection,
+    notes: Vec<NoteId>,
+) -> usize {
+    let edit = ChartEdit::notes(label, beatmap, &selection.notes, &notes);
+    let merged = history.apply(beatmap, edit);
+    selection.notes = notes;
+    merged
+}
+
+fn with_merged(message: String, merged: usize) -> String {
+    if merged == 0 {
+        message
+    } else {
+        format!(
+            "{}, {} of them landed on notes already in the chart and were merged into them",
+            message, merged
+        )
+    }
+}
+
+fn apply_action(
+    action: SelectionAction,
+    beatmap: &mut Beatmap,
+    history: &mut EditHistory,
+    selection: &mut TimelineSelection,
+    clipboard: &mut Clipboard,
+    cursor: usize,
+) -> Result<String, String> {
+    // undo can take selected notes out of the chart
+    selection.notes.retain(|note| beatmap.notes.contains(note));
+    let needs_selection = !matches!(
+        action,
+        SelectionAction::Select { .. } | SelectionAction::Paste
+    );
+    if needs_selection && selection.notes.is_empty() {
+        return Err("Nothing selected".into());
+    }
+    let count = selection.notes.len();
+
+    match action {
+        SelectionAction::Select { from, to } => {
+            selection.notes = beatmap
+                .notes
+                .iter()
+                .filter(|note| note.timing >= from && note.timing <= to)
+                .cloned()
+                .collect();
+            Ok(format!("Selected {} notes", selection.notes.len()))
+        }
+        SelectionAction::Copy | SelectionAction::Cut => {
+            let start = selection.notes.iter().map(|note| note.timing).min();
+            clipboard.notes = selection
+                .notes
+                .iter()
+                .map(|note| note.moved(note.timing - start.unwrap_or(0), note.lane))
+                .collect();
+
+            if matches!(action, SelectionAction::Cut) {
+                transform("cut", beatmap, history, selection, vec![]);
+                Ok(format!("Cut {} notes", count))
+            } else {
+                Ok(format!("Copied {} notes", count))
+            }
+        }
+        SelectionAction::Paste => {
+            if clipboard.notes.is_empty() {
+                return Err("Nothing to paste".into());
+            }
+            let notes: Vec<NoteId> = clipboard
+                .notes
+                .iter()
+                .map(|note| note.moved(cursor + note.timing, note.lane))
+                .collect();
+
+            let edit = ChartEdit::notes("paste", beatmap, &[], &notes);
+            let merged = history.apply(beatmap, edit);
+            selection.notes = notes;
+            let message = format!("Pasted {} notes at frame {}", clipboard.notes.len(), cursor);
+            Ok(with_merged(message, merged))
+        }
+        SelectionAction::Delete => {
+            transform("delete", beatmap, history, selection, vec![]);
+            Ok(format!("Deleted {} notes", count))
+        }
+        SelectionAction::Mirror => {
+            let notes = mirrored(&selection.notes);
+            let merged = transform("mirror", beatmap, history, selection, notes);
+            Ok(with_merged(format!("Mirrored {} notes", count), merged))
+        }
+        SelectionAction::Shift { lanes } => {
+            let notes = shifted(&selection.notes, lanes)
+                .ok_or("The selection would be shifted past the outer lanes")?;
+            let merged = transform("shift lanes", beatmap, history, selection, notes);
+            let message = format!("Shifted {} notes by {} lanes", count, lanes);
+            Ok(with_merged(message, merged))
+        }
+        SelectionAction::Stretch { factor } => {
+            if !factor.is_finite() || factor <= 0.0 {
+                return Err("The stretch factor has to be a number above 0".into());
+            }
+            let notes = stretched(&selection.notes, factor)
+                .ok_or("The stretched selection would run past the end of the chart")?;
+            let merged = transform("stretch", beatmap, history, selection, notes);
+            let message = format!("Stretched {} notes by {}", count, factor);
+            Ok(with_merged(message, merged))
+        }
+    }
+}
+
+// ctrl + c, x and v for the clipboard, ctrl + left and right shift lanes
+fn selection_hotkeys(
+    keys: Res<ButtonInput<KeyCode>>,
+    frames: Res<FramesCount>,
+    mut beatmap: ResMut<Beatmap>,
+    mut history: ResMut<EditHistory>,
+    mut selection: ResMut<TimelineSelection>,
+    mut clipboard: ResMut<Clipboard>,
+) {
+    if !(keys.pressed(KeyCode::ControlLeft) || keys.pressed(KeyCode::ControlRight)) {
+        return;
+    }
+
+    let action = if keys.just_pressed(KeyCode::KeyC) {
+        SelectionAction::Copy
+    } else if keys.just_pressed(KeyCode::KeyX) {
+        SelectionAction::Cut
+    } else if keys.just_pressed(KeyCode::KeyV) {
+        SelectionAction::Paste
+    } else if keys.just_pressed(KeyCode::ArrowLeft) {
+        SelectionAction::Shift { lanes: -1 }
+    } else if keys.just_pressed(KeyCode::ArrowRight) {
+        SelectionAction::Shift { lanes: 1 }
+    } else {
+        return;
+    };
+
+    if let Err(e) = apply_action(
+        action,
+        &mut beatmap,
+        &mut history,
+        &mut selection,
+        &mut clipboard,
+        frames.count,
+    ) {
+        warn!("{}", e);
+    }
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "selection")]
+struct SelectionCommand {
+    #[command(subcommand)]
+    action: SelectionAction,
+}
+
+fn selection_command(
+    mut log: ConsoleCommand<SelectionCommand>,
+    frames: Res<FramesCount>,
+    mut beatmap: ResMut<Beatmap>,
+    mut history: ResMut<EditHistory>,
+    mut selection: ResMut<TimelineSelection>,
+    mut clipboard: ResMut<Clipboard>,
+) {
+    if let Some(Ok(SelectionCommand { action })) = log.take() {
+        match apply_action(
+            action,
+            &mut beatmap,
+            &mut history,
+            &mut selection,
+            &mut clipboard,
+            frames.count,
+        ) {
+            Ok(message) | Err(message) => log.reply(message),
+        }
+    }
+}
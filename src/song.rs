@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use bevy::{
+    audio::{AddAudioSource, Decodable, Source},
+    prelude::*,
+};
+use bevy_console::{AddConsoleCommand, ConsoleCommand};
+use clap::Parser;
+
+pub struct SongPlugin;
+
+impl Plugin for SongPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<SongClip>()
+            .insert_resource(PlaybackRate(1.0))
+            .add_systems(
+                Update,
+                apply_playback_rate.run_if(resource_changed::<PlaybackRate>),
+            )
+            .add_console_command::<RateCommand, _>(rate_command);
+    }
+}
+
+// song speed for practice and charting, the game clock follows it so frames,
+// scrolling and recorded timings all stay in song time
+#[derive(Resource)]
+pub struct PlaybackRate(pub f32);
+
+const MIN_RATE: f32 = 0.5;
+const MAX_RATE: f32 = 2.0;
+
+impl PlaybackRate {
+    pub fn settings(&self, settings: PlaybackSettings) -> PlaybackSettings {
+        settings.with_speed(self.0)
+    }
+}
+
+// songs playing at the playback rate, as opposed to hitsounds
+#[derive(Component)]
+pub struct RateScaled;
+
+fn apply_playback_rate(
+    rate: Res<PlaybackRate>,
+    mut time: ResMut<Time<Virtual>>,
+    sinks: Query<&AudioSink, With<RateScaled>>,
+) {
+    // fixed updates run off virtual time, so this speeds up the frame clock too
+    time.set_relative_speed(rate.0);
+    for sink in sinks.iter() {
+        sink.set_speed(rate.0);
+    }
+}
+
+#[derive(Parser, ConsoleCommand)]
+#[command(name = "rate")]
+struct RateCommand {
+    // 0.5 to 2.0, 1.0 is normal speed
+    rate: Option<f32>,
+}
+
+fn rate_command(mut log: ConsoleCommand<RateCommand>, mut playback_rate: ResMut<PlaybackRate>) {
+    if let Some(Ok(RateCommand { rate })) = log.take() {
+        if let Some(rate) = rate {
+            if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+                log.reply(format!(
+                    "Playback rate has to be between {} and {}",
+                    MIN_RATE, MAX_RATE
+                ));
+                return;
+            }
+            playback_rate.0 = rate;
+        }
+        log.reply(format!("Playing at {}x", playback_rate.0));
+    }
+}
+
+// a song that starts playing partway through, bevy's AudioSink can't seek on its own,
+// or after some silence so the first notes have time to scroll in
+#[derive(Asset, TypePath)]
+pub struct SongClip {
+    song: AudioSource,
+    start: Duration,
+    lead_in: Duration,
+}
+
+impl SongClip {
+    pub fn new(song: &AudioSource, start: Duration) -> Self {
+        SongClip {
+            song: song.clone(),
+            start,
+            lead_in: Duration::ZERO,
+        }
+    }
+
+    pub fn with_lead_in(song: &AudioSource, lead_in: Duration) -> Self {
+        SongClip {
+            song: song.clone(),
+            start: Duration::ZERO,
+            lead_in,
+        }
+    }
+}
+
+impl Decodable for SongClip {
+    type DecoderItem = <AudioSource as Decodable>::DecoderItem;
+    type Decoder = Box<dyn Source<Item = Self::DecoderItem> + Send>;
+
+    fn decoder(&self) -> Self::Decoder {
+        Box::new(
+            self.song
+                .decoder()
+                .skip_duration(self.start)
+                .delay(self.lead_in),
+        )
+    }
+}
+
+// song time for a position on the frame clock
+pub fn frames_to_duration(frames: usize) -> Duration {
+    Duration::from_secs_f64(frames as f64 / 60.0)
+}
@@ -0,0 +1,471 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use bevy_console::ConsoleOpen;
+use machitan_format::{
+    timing::{beat_lines, quantise_to, snap_frames, timing_point_at, BeatLine},
+    NoteId, NoteLane, LANES,
+};
+
+use crate::{
+    editor::{
+        BeatmapRecord, EditorCamera, EditorPlayback, EditorSet, EditorTag, PlaybackEvent,
+        SnapSettings,
+    },
+    history::{ChartEdit, EditHistory},
+    note::{lane_transforms, Beatmap, LANE_VERT_POS},
+    FramesCount,
+};
+
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimelineView { zoom: 4.0 })
+            .init_resource::<TimelineSelection>()
+            .init_resource::<TimelineDrag>()
+            .add_systems(
+                Update,
+                (
+                    scrub_timeline,
+                    edit_timeline,
+                    draw_timeline_grid,
+                    draw_timeline_notes,
+                )
+                    .chain()
+                    .in_set(EditorSet),
+            );
+    }
+}
+
+// the playhead sits on the lane frames, the rest of the chart scrolls past it
+const PLAYHEAD_Y: f32 = LANE_VERT_POS;
+pub(crate) const LANE_WIDTH: f32 = 144.0;
+const NOTE_SIZE: Vec2 = Vec2::new(128.0, 16.0);
+const HOLD_WIDTH: f32 = 64.0;
+
+// pixels per frame
+#[derive(Resource)]
+pub(crate) struct TimelineView {
+    pub zoom: f32,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct TimelineSelection {
+    pub notes: Vec<NoteId>,
+}
+
+#[derive(Resource, Default)]
+enum TimelineDrag {
+    #[default]
+    None,
+    Move {
+        start: Vec2,
+    },
+    Select {
+        start: Vec2,
+    },
+    // ctrl + drag from an empty spot draws a hold
+    Hold {
+        start: Vec2,
+    },
+}
+
+#[derive(Component)]
+struct TimelineNote;
+
+pub(crate) fn timing_to_y(timing: usize, cursor: usize, zoom: f32) -> f32 {
+    PLAYHEAD_Y + (timing as f32 - cursor as f32) * zoom
+}
+
+fn y_to_timing(y: f32, cursor: usize, zoom: f32) -> Option<usize> {
+    let timing = cursor as f32 + (y - PLAYHEAD_Y) / zoom;
+    (timing >= 0.).then_some(timing.round() as usize)
+}
+
+pub(crate) fn lane_x(lane: &NoteLane) -> f32 {
+    lane_transforms(lane, None).translation.x
+}
+
+fn x_to_lane(x: f32) -> Option<NoteLane> {
+    LANES
+        .iter()
+        .find(|lane| (lane_x(lane) - x).abs() <= LANE_WIDTH / 2.)
+        .copied()
+}
+
+// size of one snap step around a frame, for scrolling and dragging by whole grid lines
+fn snap_step(beatmap: &Beatmap, snap: &SnapSettings, timing: usize) -> f32 {
+    let point = timing_point_at(&beatmap.timing_points, timing as f64);
+    snap_frames(point.bpm, snap.divisor) as f32
+}
+
+fn cursor_world(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform), With<EditorCamera>>,
+) -> Option<Vec2> {
+    let window = windows.get_single().ok()?;
+    let (camera, transform) = cameras.get_single().ok()?;
+    camera.viewport_to_world_2d(transform, window.cursor_position()?)
+}
+
+fn note_at(beatmap: &Beatmap, pos: Vec2, cursor: usize, zoom: f32) -> Option<NoteId> {
+    beatmap
+        .notes
+        .iter()
+        .find(|note| {
+            let head = timing_to_y(note.timing, cursor, zoom);
+            let tail = timing_to_y(note.last_frame(), cursor, zoom);
+            (lane_x(&note.lane) - pos.x).abs() <= NOTE_SIZE.x / 2.
+                && pos.y >= head - NOTE_SIZE.y / 2.
+                && pos.y <= tail + NOTE_SIZE.y / 2.
+        })
+        .cloned()
+}
+
+// where notes end up after being dragged, kept inside the lanes and after the song start
+pub(crate) fn moved_notes(notes: &[NoteId], frames: isize, lanes: isize) -> Vec<NoteId> {
+    notes
+        .iter()
+        .map(|note| {
+            let lane = (note.lane.index() as isize + lanes).clamp(0, LANES.len() as isize - 1);
+            note.moved(
+                (note.timing as isize + frames).max(0) as usize,
+                LANES[lane as usize],
+            )
+        })
+        .collect()
+}
+
+// mouse wheel scrubs the song position, ctrl + wheel zooms
+fn scrub_timeline(
+    mut ev_wheel: EventReader<MouseWheel>,
+    keys: Res<ButtonInput<KeyCode>>,
+    beatmap: Res<Beatmap>,
+    snap: Res<SnapSettings>,
+    mut view: ResMut<TimelineView>,
+    mut frames: ResMut<FramesCount>,
+    mut ev_playback: EventWriter<PlaybackEvent>,
+) {
+    let zooming = keys.pressed(KeyCode::ControlLeft) || keys.pressed(KeyCode::ControlRight);
+    let mut scrubbed = false;
+
+    for ev in ev_wheel.read() {
+        let lines = match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            MouseScrollUnit::Pixel => ev.y / 20.,
+        };
+
+        if zooming {
+            view.zoom = (view.zoom * 1.1f32.powf(lines)).clamp(0.5, 32.);
+        } else {
+            let step = snap_step(&beatmap, &snap, frames.count);
+            let count = (frames.count as f32 + lines * step).max(0.).round() as usize;
+            frames.count = quantise_to(&beatmap.timing_points, count, snap.divisor);
+            scrubbed = true;
+        }
+    }
+
+    if scrubbed {
+        ev_playback.send(PlaybackEvent::Seek);
+    }
+}
+
+// left click places or selects notes, dragging moves the selection or draws a selection box,
+// right click or delete removes notes
+#[allow(clippy::too_many_arguments)]
+fn edit_timeline(
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<EditorCamera>>,
+    view: Res<TimelineView>,
+    frames: Res<FramesCount>,
+    playback: Res<EditorPlayback>,
+    snap: Res<SnapSettings>,
+    mut drag: ResMut<TimelineDrag>,
+    mut selection: ResMut<TimelineSelection>,
+    mut history: ResMut<EditHistory>,
+    mut beatmap: ResMut<Beatmap>,
+    mut gizmos: Gizmos,
+    console: Res<ConsoleOpen>,
+) {
+    // undo and redo can take selected notes out of the chart
+    if beatmap.is_changed() && selection.notes.iter().any(|n| !beatmap.notes.contains(n)) {
+        selection.notes.retain(|n| beatmap.notes.contains(n));
+    }
+
+    if keys.just_pressed(KeyCode::Delete) && !console.open && !selection.notes.is_empty() {
+        let notes = std::mem::take(&mut selection.notes);
+        let edit = ChartEdit::notes("delete", &beatmap, &notes, &[]);
+        history.apply(&mut beatmap, edit);
+    }
+
+    let Some(pos) = cursor_world(&windows, &cameras) else {
+        return;
+    };
+    let shift = keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight);
+    // hold alt to place and move notes off the grid
+    let free = keys.pressed(KeyCode::AltLeft) || keys.pressed(KeyCode::AltRight);
+    let ctrl = keys.pressed(KeyCode::ControlLeft) || keys.pressed(KeyCode::ControlRight);
+    let snapped = |timing: usize| {
+        if free {
+            timing
+        } else {
+            quantise_to(&beatmap.timing_points, timing, snap.divisor)
+        }
+    };
+
+    if mouse.just_pressed(MouseButton::Left) {
+        match note_at(&beatmap, pos, frames.count, view.zoom) {
+            Some(note) => {
+                if shift {
+                    if let Some(index) = selection.notes.iter().position(|n| n == &note) {
+                        selection.notes.remove(index);
+                    } else {
+                        selection.notes.push(note);
+                    }
+                } else if !selection.notes.contains(&note) {
+                    selection.notes = vec![note];
+                }
+                *drag = TimelineDrag::Move { start: pos };
+            }
+            None if ctrl && !playback.playing => *drag = TimelineDrag::Hold { start: pos },
+            None => {
+                if !shift {
+                    selection.notes.clear();
+                }
+                *drag = TimelineDrag::Select { start: pos };
+            }
+        }
+    }
+
+    match *drag {
+        TimelineDrag::Select { start } => {
+            gizmos.rect_2d((start + pos) / 2., 0., (pos - start).abs(), Color::GOLD);
+        }
+        TimelineDrag::Hold { start } => {
+            if let Some(lane) = x_to_lane(start.x) {
+                let x = lane_x(&lane);
+                gizmos.line_2d(
+                    Vec2::new(x, start.y),
+                    Vec2::new(x, pos.y),
+                    Color::LIME_GREEN,
+                );
+            }
+        }
+        _ => {}
+    }
+
+    if mouse.just_released(MouseButton::Left) {
+        match std::mem::take(&mut *drag) {
+            TimelineDrag::Move { start } => {
+                let mut frames_moved = ((pos.y - start.y) / view.zoom).round() as isize;
+                if !free {
+                    let from = y_to_timing(start.y, frames.count, view.zoom).unwrap_or(0);
+                    let step = snap_step(&beatmap, &snap, from);
+                    frames_moved = ((frames_moved as f32 / step).round() * step).round() as isize;
+                }
+                let lanes_moved = match (x_to_lane(start.x), x_to_lane(pos.x)) {
+                    (Some(from), Some(to)) => to.index() as isize - from.index() as isize,
+                    _ => 0,
+                };
+
+                if frames_moved != 0 || lanes_moved != 0 {
+                    let notes = std::mem::take(&mut selection.notes);
+                    selection.notes = moved_notes(&notes, frames_moved, lanes_moved);
+                    let edit = ChartEdit::notes("move", &beatmap, &notes, &selection.notes);
+                    history.apply(&mut beatmap, edit);
+                }
+            }
+            TimelineDrag::Select { start } => {
+                if start.distance(pos) < 4. {
+                    // a plain click on an empty spot places a note, but not while the song runs
+                    if let (Some(lane), Some(timing), false) = (
+                        x_to_lane(pos.x),
+                        y_to_timing(pos.y, frames.count, view.zoom),
+                        playback.playing,
+                    ) {
+                        let note = NoteId::tap(snapped(timing), lane);
+                        let edit = ChartEdit::notes("place", &beatmap, &[], &[note]);
+                        history.apply(&mut beatmap, edit);
+                    }
+                } else {
+                    let min = start.min(pos);
+                    let max = start.max(pos);
+                    selection.notes.extend(
+                        beatmap
+                            .notes
+                            .iter()
+                            .filter(|note| {
+                                let x = lane_x(&note.lane);
+                                let y = timing_to_y(note.timing, frames.count, view.zoom);
+                                x >= min.x && x <= max.x && y >= min.y && y <= max.y
+                            })
+                            .cloned(),
+                    );
+                }
+            }
+            TimelineDrag::Hold { start } => {
+                if let (Some(lane), Some(from), Some(to)) = (
+                    x_to_lane(start.x),
+                    y_to_timing(start.y, frames.count, view.zoom),
+                    y_to_timing(pos.y, frames.count, view.zoom),
+                ) {
+                    // dragging down works too, the hold always starts at the earlier frame
+                    let (timing, end) = (snapped(from.min(to)), snapped(from.max(to)));
+                    let note = NoteId {
+                        timing,
+                        lane,
+                        end: (end > timing).then_some(end),
+                    };
+                    let edit = ChartEdit::notes("place", &beatmap, &[], &[note]);
+                    history.apply(&mut beatmap, edit);
+                }
+            }
+            TimelineDrag::None => {}
+        }
+    }
+
+    if mouse.just_pressed(MouseButton::Right) {
+        if let Some(note) = note_at(&beatmap, pos, frames.count, view.zoom) {
+            let edit = ChartEdit::notes("delete", &beatmap, std::slice::from_ref(&note), &[]);
+            history.apply(&mut beatmap, edit);
+            selection.notes.retain(|n| n != &note);
+        }
+    }
+}
+
+pub(crate) fn visible_range(window: &Window, cursor: usize, zoom: f32) -> (usize, usize) {
+    let half_height = window.height() / 2.;
+    let first = cursor as f32 + (-half_height - PLAYHEAD_Y) / zoom;
+    let last = cursor as f32 + (half_height - PLAYHEAD_Y) / zoom;
+    (first.max(0.) as usize, last.max(0.) as usize)
+}
+
+fn draw_timeline_grid(
+    mut gizmos: Gizmos,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    frames: Res<FramesCount>,
+    view: Res<TimelineView>,
+    beatmap: Res<Beatmap>,
+    snap: Res<SnapSettings>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let left = lane_x(&LANES[0]) - LANE_WIDTH / 2.;
+    let right = lane_x(&LANES[LANES.len() - 1]) + LANE_WIDTH / 2.;
+    let (first, last) = visible_range(window, frames.count, view.zoom);
+
+    for (timing, kind) in beat_lines(
+        &beatmap.timing_points,
+        first as f64,
+        last as f64,
+        snap.divisor,
+    ) {
+        let color = match kind {
+            BeatLine::Measure => Color::GRAY,
+            BeatLine::Beat => Color::DARK_GRAY,
+            BeatLine::Snap => Color::rgb(0.15, 0.15, 0.15),
+        };
+        let y = PLAYHEAD_Y + (timing as f32 - frames.count as f32) * view.zoom;
+        gizmos.line_2d(Vec2::new(left, y), Vec2::new(right, y), color);
+    }
+
+    gizmos.line_2d(
+        Vec2::new(left, PLAYHEAD_Y),
+        Vec2::new(right, PLAYHEAD_Y),
+        Color::RED,
+    );
+}
+
+// note sprites are rebuilt whenever the chart, selection or view changes
+#[allow(clippy::too_many_arguments)]
+fn draw_timeline_notes(
+    mut commands: Commands,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    frames: Res<FramesCount>,
+    view: Res<TimelineView>,
+    beatmap: Res<Beatmap>,
+    selection: Res<TimelineSelection>,
+    beatmap_record: Res<BeatmapRecord>,
+    playback: Res<EditorPlayback>,
+    query: Query<Entity, With<TimelineNote>>,
+) {
+    if !(frames.is_changed()
+        || view.is_changed()
+        || beatmap.is_changed()
+        || selection.is_changed()
+        || beatmap_record.is_changed())
+    {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    // the take only goes into the chart once recording stops, show it as it comes in
+    let take: &[NoteId] = if playback.recording {
+        &beatmap_record.notes
+    } else {
+        &[]
+    };
+
+    let (first, last) = visible_range(window, frames.count, view.zoom);
+    for note in beatmap
+        .notes
+        .iter()
+        .chain(take)
+        .filter(|note| note.last_frame() >= first && note.timing <= last)
+    {
+        let color = if selection.notes.contains(note) {
+            Color::GOLD
+        } else if beatmap_record.notes.contains(note) {
+            Color::LIME_GREEN
+        } else {
+            Color::ALICE_BLUE
+        };
+        // hold bodies run up from the head to the frame they're let go on
+        if note.end.is_some() {
+            let head = timing_to_y(note.timing, frames.count, view.zoom);
+            let length = note.length() as f32 * view.zoom;
+            commands.spawn((
+                EditorTag,
+                TimelineNote,
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: color.with_a(0.5),
+                        custom_size: Some(Vec2::new(HOLD_WIDTH, length)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(lane_x(&note.lane), head + length / 2., 89.),
+                    ..default()
+                },
+            ));
+        }
+        commands.spawn((
+            EditorTag,
+            TimelineNote,
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(NOTE_SIZE),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    lane_x(&note.lane),
+                    timing_to_y(note.timing, frames.count, view.zoom),
+                    90.,
+                ),
+                ..default()
+            },
+        ));
+    }
+}
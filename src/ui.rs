@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::ApplicationState;
+use crate::{loading::LoadingTarget, ApplicationState};
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct UiSet;
@@ -9,49 +9,92 @@ pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, menu_setup.in_set(UiSet))
-            .add_systems(Update, (clear_menu, main_menu).in_set(UiSet));
+        app.add_systems(OnEnter(ApplicationState::Menu), menu_setup)
+            .add_systems(OnExit(ApplicationState::Menu), clear_menu)
+            .add_systems(
+                Update,
+                main_menu
+                    .in_set(UiSet)
+                    .run_if(in_state(ApplicationState::Menu)),
+            );
+    }
+}
+
+#[derive(Component)]
+struct MenuTag;
+
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    Play,
+    Editor,
+}
+
+impl MenuButton {
+    fn target(&self) -> ApplicationState {
+        match self {
+            MenuButton::Play => ApplicationState::InGame,
+            MenuButton::Editor => ApplicationState::Editor,
+        }
+    }
+
+    fn label(&self, hovered: bool) -> &'static str {
+        match (self, hovered) {
+            (MenuButton::Play, false) => "Start Game",
+            (MenuButton::Play, true) => "GANBARUZO!",
+            (MenuButton::Editor, false) => "Editor",
+            (MenuButton::Editor, true) => "Chart it!",
+        }
     }
 }
 
 fn menu_setup(mut commands: Commands, server: Res<AssetServer>) {
     commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(100.0),
-                height: Val::Percent(100.0),
-                align_items: AlignItems::Center,
-                justify_content: JustifyContent::Center,
+        .spawn((
+            MenuTag,
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(20.0),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        })
+        ))
         .with_children(|parent| {
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        width: Val::Px(150.0),
-                        height: Val::Px(65.0),
-                        border: UiRect::all(Val::Px(5.0)),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..default()
-                    },
-                    border_color: BorderColor(Color::SEA_GREEN),
-                    background_color: BackgroundColor(Color::ALICE_BLUE),
-                    ..default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Button",
-                        TextStyle {
-                            // font: server.load("fonts/TitilliumWeb-SemiBold.ttf"),
-                            font_size: 40.0,
-                            color: Color::rgb(0.1, 0.1, 0.1),
+            for button in [MenuButton::Play, MenuButton::Editor] {
+                parent
+                    .spawn((
+                        button,
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(150.0),
+                                height: Val::Px(65.0),
+                                border: UiRect::all(Val::Px(5.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            border_color: BorderColor(Color::SEA_GREEN),
+                            background_color: BackgroundColor(Color::ALICE_BLUE),
                             ..default()
                         },
-                    ));
-                });
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            button.label(false),
+                            TextStyle {
+                                // font: server.load("fonts/TitilliumWeb-SemiBold.ttf"),
+                                font_size: 40.0,
+                                color: Color::rgb(0.1, 0.1, 0.1),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
         });
 }
 
@@ -62,31 +105,30 @@ fn main_menu(
             &mut BackgroundColor,
             &mut BorderColor,
             &Children,
+            &MenuButton,
         ),
         (Changed<Interaction>, With<Button>),
     >,
     mut text_query: Query<&mut Text>,
     state: Res<State<ApplicationState>>,
+    mut target: ResMut<LoadingTarget>,
     mut next_state: ResMut<NextState<ApplicationState>>,
 ) {
-    for (interaction, mut color, mut border, children) in interaction_query.iter_mut() {
+    for (interaction, mut color, mut border, children, button) in interaction_query.iter_mut() {
         let mut text = text_query.get_mut(children[0]).unwrap();
         match *interaction {
-            Interaction::Pressed => next_state.set(ApplicationState::InGame),
-            Interaction::Hovered => text.sections[0].value = "GANBARUZO!".into(),
-            Interaction::None => text.sections[0].value = "Start Game".into(),
+            Interaction::Pressed => {
+                target.0 = button.target();
+                next_state.set(ApplicationState::Loading);
+            }
+            Interaction::Hovered => text.sections[0].value = button.label(true).into(),
+            Interaction::None => text.sections[0].value = button.label(false).into(),
         }
     }
 }
 
-fn clear_menu(
-    mut commands: Commands,
-    mut query: Query<Entity, With<Node>>,
-    state: Res<State<ApplicationState>>,
-) {
-    if state.get() != &ApplicationState::Menu {
-        for entity in query.iter_mut() {
-            commands.entity(entity).despawn();
-        }
+fn clear_menu(mut commands: Commands, query: Query<Entity, With<MenuTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
     }
 }
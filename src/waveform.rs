@@ -0,0 +1,118 @@
+use bevy::{
+    audio::{CpalSample, Decodable, Source},
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    window::PrimaryWindow,
+};
+use machitan_format::{timing::FRAMES_PER_SECOND, LANES};
+
+use crate::{
+    editor::EditorSet,
+    loading::GameAssets,
+    timeline::{lane_x, timing_to_y, visible_range, TimelineView, LANE_WIDTH},
+    FramesCount,
+};
+
+pub struct WaveformPlugin;
+
+impl Plugin for WaveformPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Waveform>().add_systems(
+            Update,
+            (decode_waveform, draw_waveform).chain().in_set(EditorSet),
+        );
+    }
+}
+
+// loudest sample in every frame of the song, decoded once per song in the background
+#[derive(Resource, Default)]
+struct Waveform {
+    song: Option<AssetId<AudioSource>>,
+    peaks: Vec<f32>,
+    task: Option<Task<Vec<f32>>>,
+}
+
+fn waveform_peaks(song: &AudioSource) -> Vec<f32> {
+    let decoder = song.decoder();
+    let samples_per_frame =
+        decoder.sample_rate() as f64 * decoder.channels() as f64 / FRAMES_PER_SECOND;
+
+    let mut peaks: Vec<f32> = vec![];
+    for (index, sample) in decoder.enumerate() {
+        let frame = (index as f64 / samples_per_frame) as usize;
+        if frame >= peaks.len() {
+            peaks.resize(frame + 1, 0.);
+        }
+        peaks[frame] = peaks[frame].max(sample.to_sample::<f32>().abs());
+    }
+    peaks
+}
+
+fn decode_waveform(
+    assets: Res<GameAssets>,
+    songs: Res<Assets<AudioSource>>,
+    mut waveform: ResMut<Waveform>,
+) {
+    let Some(handle) = &assets.song else {
+        return;
+    };
+
+    // a different song was loaded, dropping the old task cancels it
+    if waveform.song != Some(handle.id()) {
+        let Some(song) = songs.get(handle) else {
+            return;
+        };
+        let song = song.clone();
+        waveform.song = Some(handle.id());
+        waveform.peaks.clear();
+        waveform.task =
+            Some(AsyncComputeTaskPool::get().spawn(async move { waveform_peaks(&song) }));
+    }
+
+    if let Some(task) = &mut waveform.task {
+        if let Some(peaks) = block_on(future::poll_once(task)) {
+            waveform.peaks = peaks;
+            waveform.task = None;
+        }
+    }
+}
+
+// drawn mirrored behind the lanes, one line per frame or per group of frames when zoomed out
+fn draw_waveform(
+    mut gizmos: Gizmos,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    frames: Res<FramesCount>,
+    view: Res<TimelineView>,
+    waveform: Res<Waveform>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if waveform.peaks.is_empty() {
+        return;
+    }
+
+    let half_width = lane_x(&LANES[LANES.len() - 1]) + LANE_WIDTH / 2.;
+    let step = (1. / view.zoom).ceil().max(1.) as usize;
+    let (first, last) = visible_range(window, frames.count, view.zoom);
+    let last = last.min(waveform.peaks.len() - 1);
+
+    for timing in (first - first % step..=last).step_by(step) {
+        let end = (timing + step).min(waveform.peaks.len());
+        let Some(peak) = waveform.peaks[timing.min(end)..end]
+            .iter()
+            .copied()
+            .reduce(f32::max)
+        else {
+            continue;
+        };
+
+        let y = timing_to_y(timing, frames.count, view.zoom);
+        let x = peak * half_width;
+        gizmos.line_2d(
+            Vec2::new(-x, y),
+            Vec2::new(x, y),
+            Color::rgba(0.2, 0.4, 0.8, 0.35),
+        );
+    }
+}